@@ -1,133 +1,778 @@
-#import bevy_shader_utils::simplex_noise_3d simplex_noise_3d
-
-struct unsereigenerty {
-    player_position: vec2<f32>,
-    click: u32,
-}
-
-@group(0) @binding(0)
-var height_in: texture_storage_2d<r32float, read>;
-@group(0) @binding(1)
-var height_out: texture_storage_2d<r32float, write>;
-@group(0) @binding(2)
-var velocity: texture_storage_2d<r32float, read_write>;
-@group(0) @binding(3)
-var terrain_height_in: texture_storage_2d<r32float, read_write>;
-@group(0) @binding(4)
-var<uniform> uniforms : unsereigenerty;
-
-@compute @workgroup_size(8, 8, 1)
-fn init(@builtin(global_invocation_id) invocation_id: vec3<u32>, @builtin(num_workgroups) num_workgroups: vec3<u32>) {
-    let location = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
-
-    let location_for_noise = vec3<f32>(f32(invocation_id.x) * 0.0052, f32(invocation_id.y) * 0.0052, 1.0);
-    let noise = simplex_noise_3d(location_for_noise);
-    var height = noise * 1.0 - 0.777;
-    if (location.y < 100) {
-        height = 0.0;
-    }
-
-    textureStore(height_out, location, vec4<f32>(max(height, 0.0), 0.0, 0.0, 1.0));
-    textureStore(velocity, location, vec4(0.0, 0.0, 0.0, 1.0));
-
-    let location_for_noise_for_terrain = vec3<f32>(f32(invocation_id.x) * 0.0052, f32(invocation_id.y) * 0.0152, 0.0);
-    let noise_for_terrain = simplex_noise_3d(location_for_noise_for_terrain);
-    let height_for_terrain = noise_for_terrain + 1.5;
-    textureStore(terrain_height_in, location, vec4<f32>(max(height_for_terrain, 0.0), 0.0, 0.0, 1.0));
-}
-
-fn get_height(location: vec2<i32>, offset_x: i32, offset_y: i32, center_height: f32, dim: vec2<u32>) -> f32 {
-    let loc = location + vec2<i32>(offset_x, offset_y);
-    if (loc.x < 0 || loc.y < 0 || loc.x >= i32(dim.x) || loc.y >= i32(dim.y)) {
-        return center_height;
-    }
-    let value: vec4<f32> = textureLoad(height_in, loc);
-    return value.x;
-}
-
-fn get_terrain_height(location: vec2<i32>, offset_x: i32, offset_y: i32, center_height: f32, dim: vec2<u32>) -> f32 {
-    let loc = location + vec2<i32>(offset_x, offset_y);
-    if (loc.x < 0 || loc.y < 0 || loc.x >= i32(dim.x) || loc.y >= i32(dim.y)) {
-        return center_height;
-    }
-    let value: vec4<f32> = textureLoad(terrain_height_in, loc);
-    return value.x;
-}
-
-fn get_vel(location: vec2<i32>, offset_x: i32, offset_y: i32) -> f32 {
-    let value: vec4<f32> = textureLoad(velocity, location + vec2<i32>(offset_x, offset_y));
-    return value.x;
-}
-
-fn cell_flow(w0: f32, h0: f32, w1: f32, h1: f32) -> f32
-{
-    var diff = (w1 + h1) - (w0 + h0);
-    // var diff = (w1) - (w0);
-    var drop = 0.0;
-
-    if ((w1 < 0.001 && h1 > w0 + h0) || (w0 < 0.001 && h0 > w1 + h1) || abs(diff) < 0.001) {
-        return (drop);
-    }
-    return diff;
-    // if (w0 + h0 > w1 + h1)
-    // {
-    //     return (-diff );
-    // }
-    // if (w0 + h0 < w1 + h1)
-    // {
-    //     return  (diff);
-    // }
-    // return (0.0);
-}
-
-@compute @workgroup_size(8, 8, 1)
-fn update(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
-    let location = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
-
-    let height0 = textureLoad(height_in, location).x;
-    let dim = textureDimensions(height_in);
-    let height1 = get_height(location,  1,  0, height0, dim);
-    let height2 = get_height(location, -1,  0, height0, dim);
-    let height3 = get_height(location,  0,  1, height0, dim);
-    let height4 = get_height(location,  0, -1, height0, dim);
-
-    let terrain_height0 = textureLoad(terrain_height_in, location).x;
-    let terrain_dim = textureDimensions(terrain_height_in);
-    let terrain_height1 = get_terrain_height(location,  1,  0, terrain_height0, terrain_dim);
-    let terrain_height2 = get_terrain_height(location, -1,  0, terrain_height0, terrain_dim);
-    let terrain_height3 = get_terrain_height(location,  0,  1, terrain_height0, terrain_dim);
-    let terrain_height4 = get_terrain_height(location,  0, -1, terrain_height0, terrain_dim);
-
-    let dt = 1.0 / 60.0;
-    let damping = 0.9971349;
-    let k = 50.15820;
-
-    let attracking_point = uniforms.player_position;
-    let uv = vec2(f32(location.x) / f32(dim.x), f32(location.y) / f32(dim.y));
-    let v: vec2<f32> = attracking_point - uv;
-    var attracting_force = 0.0;
-    if (uniforms.click == u32(1) && length(v) < 0.04) {
-        attracting_force = min(20.0, 1.0/((abs(v.x * v.x * v.x) + abs(v.y * v.y * v.y)))) / 10000.0;
-    }
-
-    // Calculate the total height difference from neighbors
-    // let accel = k * ((height1) + (height2) + (height3) + (height4) - 4.0 * (height0));
-    let accel = k * (cell_flow(height0, terrain_height0, height1, terrain_height1)
-                    + cell_flow(height0, terrain_height0, height2, terrain_height2)
-                    + cell_flow(height0, terrain_height0, height3, terrain_height3)
-                    + cell_flow(height0, terrain_height0, height4, terrain_height4) + attracting_force);
-	
-    // Update velocity with damping
-    let new_vel = get_vel(location, 0, 0) * damping + accel * dt;
-
-    // Mass conservation: distribute the change in height back to neighbors
-    let height_change = new_vel * dt;
-    var new_height = height0 + height_change;
-	if (terrain_height0 < 0.777) {
-		new_height -= 0.012;
-	}
-	new_height *= 0.99999;
-    textureStore(velocity, location, vec4(new_vel, 0.0, 0.0, 1.0));
-    textureStore(height_out, location, vec4(max(new_height, 0.0), 0.0, 0.0, 1.0));
-}
-
+#import bevy_shader_utils::simplex_noise_3d simplex_noise_3d
+
+// see `TerrainGenerator`, distances are in texels of the whole domain
+struct TerrainGenerator {
+    // where the noise is read from, derived from the seed
+    offset: vec2<f32>,
+    frequency: vec2<f32>,
+    octaves: u32,
+    lacunarity: f32,
+    persistence: f32,
+    amplitude: f32,
+    base_height: f32,
+    ridged: f32,
+    warp_strength: f32,
+    warp_frequency: f32,
+    island_falloff: f32,
+    river_depth: f32,
+    river_width: f32,
+    river_frequency: f32,
+    sea_level: f32,
+    puddles: f32,
+    puddle_frequency: f32,
+    dry_margin: f32,
+    domain_texels: vec2<f32>,
+}
+
+struct unsereigenerty {
+    player_position: vec2<f32>,
+    click: u32,
+    texel_size: f32,
+    // position of this chunk's first texel in the whole domain
+    texel_offset: vec2<i32>,
+    // bit per side (west, east, south, north) with an awake neighbouring chunk
+    neighbour_mask: u32,
+    // kind of boundary condition per side (west, east, south, north), see `BoundaryCondition::kind`
+    boundary_kinds: vec4<u32>,
+    // water surface height held outside of the inflow sides
+    inflow_levels: vec4<f32>,
+    // see `ErosionSettings`
+    sediment_capacity: f32,
+    erosion_rate: f32,
+    deposition_rate: f32,
+    min_slope: f32,
+    talus_slope: f32,
+    // zero while thermal erosion is off
+    thermal_rate: f32,
+    // see `DebugView`, the field as its index and the values at the ends of the ramp
+    debug_field: u32,
+    debug_min: f32,
+    debug_max: f32,
+    // what the `init` pass generates the terrain from
+    generator: TerrainGenerator,
+}
+
+const BOUNDARY_OPEN: u32 = 1u;
+const BOUNDARY_INFLOW: u32 = 3u;
+
+@group(0) @binding(0)
+var height_in: texture_storage_2d<r32float, read>;
+@group(0) @binding(1)
+var height_out: texture_storage_2d<r32float, write>;
+@group(0) @binding(2)
+var velocity: texture_storage_2d<r32float, read_write>;
+@group(0) @binding(3)
+var terrain_height_in: texture_storage_2d<r32float, read_write>;
+@group(0) @binding(4)
+var<uniform> uniforms : unsereigenerty;
+// r: water drawn by plants per second, g: how much they shade the cell against evaporation
+@group(0) @binding(5)
+var plant_water: texture_2d<f32>;
+// saturation of the soil underneath the surface water, 0 is dry and 1 is soaked
+@group(0) @binding(6)
+var soil_moisture_in: texture_storage_2d<r32float, read>;
+@group(0) @binding(7)
+var soil_moisture_out: texture_storage_2d<r32float, write>;
+// how well plants would grow in each cell, from 0 (hostile) to 1 (ideal)
+@group(0) @binding(8)
+var suitability_out: texture_storage_2d<r32float, write>;
+@group(0) @binding(9)
+var terrain_normal_out: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(10)
+var water_normal_out: texture_storage_2d<rgba16float, write>;
+// how much foam floats on the water, from 0 to 1
+@group(0) @binding(11)
+var foam_in: texture_storage_2d<r32float, read>;
+@group(0) @binding(12)
+var foam_out: texture_storage_2d<r32float, write>;
+// how much the water surface focuses the light reaching the terrain, 1 where it's flat or dry
+@group(0) @binding(13)
+var caustics_out: texture_storage_2d<r32float, write>;
+// sediment carried by the water, as the terrain height it was taken from, cleared by `pack`
+@group(0) @binding(15)
+var sediment_in: texture_storage_2d<r32float, read_write>;
+@group(0) @binding(16)
+var sediment_out: texture_storage_2d<r32float, write>;
+// how much the terrain was eroded (negative) or built up this step, applied by `erode`
+@group(0) @binding(17)
+var erosion: texture_storage_2d<r32float, read_write>;
+// how wet the surface of the terrain is, 1 under water and drying back to 0 once it recedes
+@group(0) @binding(14)
+var wetness: texture_storage_2d<r32float, read_write>;
+// false colours of the field picked by `debug_field`, written by `debug_view`
+@group(0) @binding(18)
+var debug_out: texture_storage_2d<rgba8unorm, write>;
+
+// height, terrain height and soil moisture along the west, east, south and north edges of
+// this chunk, one after the other
+@group(1) @binding(0)
+var<storage, read_write> edges_out: array<vec4<f32>>;
+// the edges of the neighbouring chunks
+@group(1) @binding(1)
+var<storage, read> halo_west: array<vec4<f32>>;
+@group(1) @binding(2)
+var<storage, read> halo_east: array<vec4<f32>>;
+@group(1) @binding(3)
+var<storage, read> halo_south: array<vec4<f32>>;
+@group(1) @binding(4)
+var<storage, read> halo_north: array<vec4<f32>>;
+// velocity and soil moisture of a sleeping chunk, packed as two halfs
+@group(1) @binding(5)
+var packed: texture_storage_2d<r32uint, read_write>;
+// lowest and highest terrain and water surface of every tile of this chunk
+@group(1) @binding(6)
+// followed by the water depth summed over the tile, its wet cells and its fastest vertical speed
+var<storage, read_write> tile_bounds_out: array<vec4<f32>>;
+
+var<workgroup> tile_min: array<vec2<f32>, 64>;
+var<workgroup> tile_max: array<vec2<f32>, 64>;
+var<workgroup> tile_water: array<vec3<f32>, 64>;
+
+// water shallower than this doesn't count as a wet cell
+const WET_DEPTH = 0.001;
+
+@compute @workgroup_size(8, 8, 1)
+fn init(@builtin(global_invocation_id) invocation_id: vec3<u32>, @builtin(num_workgroups) num_workgroups: vec3<u32>) {
+    let location = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
+    // the noise runs across the whole domain so the chunks line up
+    let global_location = location + uniforms.texel_offset;
+
+    let terrain = terrain_height(vec2<f32>(global_location));
+    let water = initial_water(vec2<f32>(global_location), terrain);
+
+    textureStore(height_out, location, vec4<f32>(water, 0.0, 0.0, 1.0));
+    textureStore(velocity, location, vec4(0.0, 0.0, 0.0, 1.0));
+    textureStore(soil_moisture_out, location, vec4(select(0.3, 1.0, water > 0.0), 0.0, 0.0, 1.0));
+    textureStore(foam_out, location, vec4(0.0, 0.0, 0.0, 1.0));
+    textureStore(wetness, location, vec4(select(0.0, 1.0, water > 0.0), 0.0, 0.0, 1.0));
+    textureStore(sediment_out, location, vec4(0.0, 0.0, 0.0, 1.0));
+    textureStore(erosion, location, vec4(0.0, 0.0, 0.0, 1.0));
+    textureStore(terrain_height_in, location, vec4<f32>(terrain, 0.0, 0.0, 1.0));
+}
+
+// Sum of the octaves of noise at `p`, each blended towards ridged noise by `ridged`, roughly
+// in -1..1.
+fn fbm(p: vec2<f32>) -> f32 {
+    let generator = uniforms.generator;
+    var sum = 0.0;
+    var total_amplitude = 0.0;
+    var amplitude = 1.0;
+    var frequency = 1.0;
+    for (var octave = 0u; octave < generator.octaves; octave++) {
+        // every octave reads its own slice of the noise
+        let noise = simplex_noise_3d(vec3<f32>(p * frequency, f32(octave) * 17.0));
+        // folding the noise at zero turns its zero crossings into crests
+        let ridged = 1.0 - 2.0 * abs(noise);
+        sum += mix(noise, ridged, generator.ridged) * amplitude;
+        total_amplitude += amplitude;
+        amplitude *= generator.persistence;
+        frequency *= generator.lacunarity;
+    }
+    return sum / max(total_amplitude, 0.0001);
+}
+
+// Terrain height the `init` pass starts out with at the texel `texel` of the whole domain.
+fn terrain_height(texel: vec2<f32>) -> f32 {
+    let generator = uniforms.generator;
+    var p = texel + generator.offset;
+    if (generator.warp_strength != 0.0) {
+        let q = p * generator.warp_frequency;
+        p += generator.warp_strength * vec2<f32>(
+            simplex_noise_3d(vec3<f32>(q, 3.1)),
+            simplex_noise_3d(vec3<f32>(q, 7.7))
+        );
+    }
+    var height = generator.base_height + generator.amplitude * fbm(p * generator.frequency);
+
+    // sink towards the edges of the domain, measured from its centre to the middle of a side
+    let from_centre = length(texel / generator.domain_texels - 0.5) * 2.0;
+    height -= generator.island_falloff * smoothstep(0.4, 1.0, from_centre);
+
+    // river beds along the zero crossings of a slow noise
+    if (generator.river_depth != 0.0) {
+        let river = abs(simplex_noise_3d(vec3<f32>(p * generator.river_frequency, 11.0)));
+        height -= generator.river_depth * (1.0 - smoothstep(0.0, generator.river_width, river));
+    }
+    return max(height, 0.0);
+}
+
+// Water height the `init` pass starts out with on top of `terrain` at the texel `texel` of the
+// whole domain.
+fn initial_water(texel: vec2<f32>, terrain: f32) -> f32 {
+    let generator = uniforms.generator;
+    let sea = max(generator.sea_level - terrain, 0.0);
+
+    let p = (texel + generator.offset) * generator.puddle_frequency;
+    // only the tops of the noise stick out as puddles
+    var puddle = max(generator.puddles * simplex_noise_3d(vec3<f32>(p, 1.0)) - 0.777, 0.0);
+    if (texel.y < generator.dry_margin) {
+        puddle = 0.0;
+    }
+    return sea + puddle;
+}
+
+// Water height, terrain height and soil moisture of the cell at `loc` inside this chunk.
+fn cell(loc: vec2<i32>) -> vec4<f32> {
+    return vec4(
+        textureLoad(height_in, loc).x,
+        textureLoad(terrain_height_in, loc).x,
+        textureLoad(soil_moisture_in, loc).x,
+        0.0
+    );
+}
+
+// The cell just outside of this chunk at `location + offset`, as water height, terrain height
+// and soil moisture. Taken from the edge of the neighbouring chunk if it is awake, otherwise
+// made up by the boundary condition of that side.
+fn outside(location: vec2<i32>, offset: vec2<i32>, dim: vec2<u32>) -> vec4<f32> {
+    let loc = location + offset;
+    let edge_length = i32(max(dim.x, dim.y));
+    var side = 0u;
+    if (loc.x >= i32(dim.x)) {
+        side = 1u;
+    } else if (loc.y < 0) {
+        side = 2u;
+    } else if (loc.y >= i32(dim.y)) {
+        side = 3u;
+    }
+    if ((uniforms.neighbour_mask & (1u << side)) != 0u) {
+        switch side {
+            case 0u: { return halo_west[edge_length + loc.y]; }
+            case 1u: { return halo_east[loc.y]; }
+            case 2u: { return halo_south[3 * edge_length + loc.x]; }
+            default: { return halo_north[2 * edge_length + loc.x]; }
+        }
+    }
+
+    let center = cell(location);
+    let kind = uniforms.boundary_kinds[side];
+    if (kind == BOUNDARY_OPEN) {
+        // continue the water surface slope past the edge, so it keeps flowing out instead of
+        // reflecting. Never deeper than the edge cell, where the surface rises towards the
+        // edge that would pump water into the domain
+        let inner = cell(clamp(location - offset, vec2<i32>(0), vec2<i32>(dim) - 1));
+        return vec4(min(center.x, max(2.0 * center.x - inner.x, 0.0)), center.yzw);
+    }
+    if (kind == BOUNDARY_INFLOW) {
+        // the water surface outside stays at a fixed height
+        return vec4(max(uniforms.inflow_levels[side] - center.y, 0.0), center.yzw);
+    }
+    // BOUNDARY_WALL, and BOUNDARY_PERIODIC when the opposite chunk is asleep: reflect
+    return center;
+}
+
+fn in_bounds(loc: vec2<i32>, dim: vec2<u32>) -> bool {
+    return loc.x >= 0 && loc.y >= 0 && loc.x < i32(dim.x) && loc.y < i32(dim.y);
+}
+
+fn get_height(location: vec2<i32>, offset_x: i32, offset_y: i32, dim: vec2<u32>) -> f32 {
+    let offset = vec2<i32>(offset_x, offset_y);
+    if (!in_bounds(location + offset, dim)) {
+        return outside(location, offset, dim).x;
+    }
+    let value: vec4<f32> = textureLoad(height_in, location + offset);
+    return value.x;
+}
+
+fn get_terrain_height(location: vec2<i32>, offset_x: i32, offset_y: i32, dim: vec2<u32>) -> f32 {
+    let offset = vec2<i32>(offset_x, offset_y);
+    if (!in_bounds(location + offset, dim)) {
+        return outside(location, offset, dim).y;
+    }
+    let value: vec4<f32> = textureLoad(terrain_height_in, location + offset);
+    return value.x;
+}
+
+fn get_soil_moisture(location: vec2<i32>, offset_x: i32, offset_y: i32, dim: vec2<u32>) -> f32 {
+    let offset = vec2<i32>(offset_x, offset_y);
+    if (!in_bounds(location + offset, dim)) {
+        return outside(location, offset, dim).z;
+    }
+    let value: vec4<f32> = textureLoad(soil_moisture_in, location + offset);
+    return value.x;
+}
+
+// Velocity isn't shared between chunks, outside of this one the water is at rest.
+fn get_vel(location: vec2<i32>, offset_x: i32, offset_y: i32) -> f32 {
+    let loc = location + vec2<i32>(offset_x, offset_y);
+    if (!in_bounds(loc, textureDimensions(velocity))) {
+        return 0.0;
+    }
+    let value: vec4<f32> = textureLoad(velocity, loc);
+    return value.x;
+}
+
+fn cell_flow(w0: f32, h0: f32, w1: f32, h1: f32) -> f32
+{
+    var diff = (w1 + h1) - (w0 + h0);
+    // var diff = (w1) - (w0);
+    var drop = 0.0;
+
+    if ((w1 < 0.001 && h1 > w0 + h0) || (w0 < 0.001 && h0 > w1 + h1) || abs(diff) < 0.001) {
+        return (drop);
+    }
+    return diff;
+    // if (w0 + h0 > w1 + h1)
+    // {
+    //     return (-diff );
+    // }
+    // if (w0 + h0 < w1 + h1)
+    // {
+    //     return  (diff);
+    // }
+    // return (0.0);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn update(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let location = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
+
+    let height0 = textureLoad(height_in, location).x;
+    let dim = textureDimensions(height_in);
+    let height1 = get_height(location,  1,  0, dim);
+    let height2 = get_height(location, -1,  0, dim);
+    let height3 = get_height(location,  0,  1, dim);
+    let height4 = get_height(location,  0, -1, dim);
+
+    let terrain_height0 = textureLoad(terrain_height_in, location).x;
+    let terrain_dim = textureDimensions(terrain_height_in);
+    let terrain_height1 = get_terrain_height(location,  1,  0, terrain_dim);
+    let terrain_height2 = get_terrain_height(location, -1,  0, terrain_dim);
+    let terrain_height3 = get_terrain_height(location,  0,  1, terrain_dim);
+    let terrain_height4 = get_terrain_height(location,  0, -1, terrain_dim);
+
+    let dt = 1.0 / 60.0;
+    let damping = 0.9971349;
+    let k = 50.15820;
+
+    let attracking_point = uniforms.player_position;
+    let uv = vec2(f32(location.x) / f32(dim.x), f32(location.y) / f32(dim.y));
+    let v: vec2<f32> = attracking_point - uv;
+    var attracting_force = 0.0;
+    if (uniforms.click == u32(1) && length(v) < 0.04) {
+        attracting_force = min(20.0, 1.0/((abs(v.x * v.x * v.x) + abs(v.y * v.y * v.y)))) / 10000.0;
+    }
+
+    // Calculate the total height difference from neighbors
+    // let accel = k * ((height1) + (height2) + (height3) + (height4) - 4.0 * (height0));
+    let accel = k * (cell_flow(height0, terrain_height0, height1, terrain_height1)
+                    + cell_flow(height0, terrain_height0, height2, terrain_height2)
+                    + cell_flow(height0, terrain_height0, height3, terrain_height3)
+                    + cell_flow(height0, terrain_height0, height4, terrain_height4) + attracting_force);
+	
+    // Update velocity with damping
+    let new_vel = get_vel(location, 0, 0) * damping + accel * dt;
+
+    // Mass conservation: distribute the change in height back to neighbors
+    let height_change = new_vel * dt;
+    var new_height = height0 + height_change;
+	if (terrain_height0 < 0.777) {
+		new_height -= 0.012;
+	}
+    let plants = textureLoad(plant_water, location, 0);
+	new_height *= 1.0 - 0.00001 * (1.0 - plants.y);
+
+    // Surface water seeps into the soil, spreads slowly to the neighbours, evaporates and
+    // gets drawn up by the plants. What the soil can't supply the roots take from the surface
+    // water.
+    let soil_capacity = 0.2;
+    let infiltration_rate = 0.05;
+    let soil_diffusion = 0.5;
+    let soil_evaporation = 0.01;
+    let soil0 = textureLoad(soil_moisture_in, location).x;
+    let soil1 = get_soil_moisture(location,  1,  0, dim);
+    let soil2 = get_soil_moisture(location, -1,  0, dim);
+    let soil3 = get_soil_moisture(location,  0,  1, dim);
+    let soil4 = get_soil_moisture(location,  0, -1, dim);
+    let infiltration = min(max(new_height, 0.0), infiltration_rate * dt * (1.0 - soil0));
+    new_height -= infiltration;
+    let wet_soil = soil0
+        + infiltration / soil_capacity
+        + soil_diffusion * dt * (soil1 + soil2 + soil3 + soil4 - 4.0 * soil0)
+        - soil_evaporation * dt * soil0 * (1.0 - plants.y);
+    let uptake = plants.x * dt;
+    let from_soil = clamp(uptake, 0.0, max(wet_soil, 0.0) * soil_capacity);
+    new_height -= min(max(new_height, 0.0), uptake - from_soil);
+    let new_soil = wet_soil - from_soil / soil_capacity;
+
+    textureStore(velocity, location, vec4(new_vel, 0.0, 0.0, 1.0));
+    textureStore(height_out, location, vec4(max(new_height, 0.0), 0.0, 0.0, 1.0));
+    textureStore(soil_moisture_out, location, vec4(clamp(new_soil, 0.0, 1.0), 0.0, 0.0, 1.0));
+
+    let foam = update_foam(location, dim, new_vel, new_height, dt);
+    textureStore(foam_out, location, vec4(foam, 0.0, 0.0, 1.0));
+
+    // the ground soaks up as soon as water covers it and dries off slowly afterwards
+    let drying_rate = 0.1;
+    let wet = max(textureLoad(wetness, location).x * exp(-drying_rate * dt), smoothstep(0.0, 0.005, new_height));
+    textureStore(wetness, location, vec4(wet, 0.0, 0.0, 1.0));
+
+    update_sediment(location, dim, max(new_height, 0.0), dt);
+}
+
+// Sediment of the cell at `p`, in texels, interpolated between the four closest cells.
+fn sample_sediment(p: vec2<f32>, dim: vec2<u32>) -> f32 {
+    let last = vec2<i32>(dim) - 1;
+    let base = vec2<i32>(floor(p));
+    let t = fract(p);
+    let s00 = textureLoad(sediment_in, clamp(base, vec2<i32>(0), last)).x;
+    let s10 = textureLoad(sediment_in, clamp(base + vec2<i32>(1, 0), vec2<i32>(0), last)).x;
+    let s01 = textureLoad(sediment_in, clamp(base + vec2<i32>(0, 1), vec2<i32>(0), last)).x;
+    let s11 = textureLoad(sediment_in, clamp(base + vec2<i32>(1, 1), vec2<i32>(0), last)).x;
+    return mix(mix(s00, s10, t.x), mix(s01, s11, t.x), t.y);
+}
+
+// Carries the sediment along with the water. Fast water running down steep terrain can hold
+// more than it carries and picks up the difference from the terrain, slower water drops what
+// it can't hold. The terrain is only changed by the `erode` pass, so the neighbours still
+// read it as it was.
+fn update_sediment(location: vec2<i32>, dim: vec2<u32>, depth: f32, dt: f32) {
+    let spacing = uniforms.texel_size;
+    let surface = surface_around(location, dim);
+    let flow = surface_flow(vec2(surface.x - surface.y, surface.z - surface.w) / (2.0 * spacing));
+    let carried = max(sample_sediment(vec2<f32>(location) - flow * dt / spacing, dim), 0.0);
+
+    let terrain0 = textureLoad(terrain_height_in, location).x;
+    let terrain_slope = length(vec2(
+        get_terrain_height(location, 1, 0, dim) - get_terrain_height(location, -1, 0, dim),
+        get_terrain_height(location, 0, 1, dim) - get_terrain_height(location, 0, -1, dim)
+    )) / (2.0 * spacing);
+    // shallow water can't hold much, dry cells nothing at all
+    let capacity = uniforms.sediment_capacity * length(flow) * max(terrain_slope, uniforms.min_slope)
+        * smoothstep(0.0, 0.01, depth);
+
+    var change = 0.0;
+    if (carried < capacity) {
+        change = -min(uniforms.erosion_rate * (capacity - carried) * dt, terrain0);
+    } else {
+        change = min(uniforms.deposition_rate * dt, 1.0) * (carried - capacity);
+    }
+    textureStore(sediment_out, location, vec4(carried - change, 0.0, 0.0, 1.0));
+    textureStore(erosion, location, vec4(change, 0.0, 0.0, 1.0));
+}
+
+// Slumps terrain steeper than the talus slope down onto its lower neighbours. Every pair of
+// neighbours works out the same amount from both sides, so what one cell loses the other
+// gains, and it's added to the change `update` worked out for `erode`.
+@compute @workgroup_size(8, 8, 1)
+fn thermal(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if (uniforms.thermal_rate <= 0.0) {
+        return;
+    }
+    let location = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
+    let dim = textureDimensions(terrain_height_in);
+    let dt = 1.0 / 60.0;
+
+    let terrain0 = textureLoad(terrain_height_in, location).x;
+    let difference = vec4(
+        get_terrain_height(location,  1,  0, dim),
+        get_terrain_height(location, -1,  0, dim),
+        get_terrain_height(location,  0,  1, dim),
+        get_terrain_height(location,  0, -1, dim)
+    ) - terrain0;
+    let excess = max(abs(difference) - uniforms.talus_slope * uniforms.texel_size, vec4(0.0));
+    // at most an eighth of the excess per pair keeps a cell from giving away more than it has
+    let moved = sign(difference) * excess * 0.125 * min(uniforms.thermal_rate * dt, 1.0);
+    let change = moved.x + moved.y + moved.z + moved.w;
+    textureStore(erosion, location, vec4(textureLoad(erosion, location).x + change, 0.0, 0.0, 1.0));
+}
+
+// Applies the terrain change worked out by `update` and `thermal`.
+@compute @workgroup_size(8, 8, 1)
+fn erode(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let location = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
+    let terrain = textureLoad(terrain_height_in, location).x + textureLoad(erosion, location).x;
+    textureStore(terrain_height_in, location, vec4(terrain, 0.0, 0.0, 1.0));
+}
+
+const FLOW_SPEED: f32 = 2.0;
+const MAX_FLOW: f32 = 4.0;
+
+// The water surface one texel towards +x, -x, +z and -z of `location`.
+fn surface_around(location: vec2<i32>, dim: vec2<u32>) -> vec4<f32> {
+    return vec4(
+        get_height(location,  1,  0, dim) + get_terrain_height(location,  1,  0, dim),
+        get_height(location, -1,  0, dim) + get_terrain_height(location, -1,  0, dim),
+        get_height(location,  0,  1, dim) + get_terrain_height(location,  0,  1, dim),
+        get_height(location,  0, -1, dim) + get_terrain_height(location,  0, -1, dim)
+    );
+}
+
+// How fast the water runs down a surface with this gradient, in world units per second. The
+// simulation only tracks how fast the surface rises and falls, so this is an estimate for
+// carrying things along.
+fn surface_flow(gradient: vec2<f32>) -> vec2<f32> {
+    let flow = -gradient * FLOW_SPEED;
+    return flow * min(1.0, MAX_FLOW / max(length(flow), 0.0001));
+}
+
+// Foam of the cell at `p`, in texels, interpolated between the four closest cells.
+fn sample_foam(p: vec2<f32>, dim: vec2<u32>) -> f32 {
+    let last = vec2<i32>(dim) - 1;
+    let base = vec2<i32>(floor(p));
+    let t = fract(p);
+    let f00 = textureLoad(foam_in, clamp(base, vec2<i32>(0), last)).x;
+    let f10 = textureLoad(foam_in, clamp(base + vec2<i32>(1, 0), vec2<i32>(0), last)).x;
+    let f01 = textureLoad(foam_in, clamp(base + vec2<i32>(0, 1), vec2<i32>(0), last)).x;
+    let f11 = textureLoad(foam_in, clamp(base + vec2<i32>(1, 1), vec2<i32>(0), last)).x;
+    return mix(mix(f00, f10, t.x), mix(f01, f11, t.x), t.y);
+}
+
+// Carries the foam along with the water running down the surface and whips up new foam where
+// the water moves fast, on wave crests and down steep falls. Foam only lives on water and
+// fades away over time.
+fn update_foam(location: vec2<i32>, dim: vec2<u32>, vertical_velocity: f32, depth: f32, dt: f32) -> f32 {
+    let decay = 0.4;
+    let velocity_threshold = 0.5;
+    let crest_threshold = 20.0;
+    let fall_threshold = 1.0;
+    let generation_rate = 3.0;
+
+    if (depth <= 0.001) {
+        return 0.0;
+    }
+
+    let surface0 = textureLoad(height_in, location).x + textureLoad(terrain_height_in, location).x;
+    let surface = surface_around(location, dim);
+    let spacing = uniforms.texel_size;
+    let gradient = vec2(surface.x - surface.y, surface.z - surface.w) / (2.0 * spacing);
+    let curvature = (surface.x + surface.y + surface.z + surface.w - 4.0 * surface0) / (spacing * spacing);
+
+    // trace back where the water came from
+    let advected = sample_foam(vec2<f32>(location) - surface_flow(gradient) * dt / spacing, dim);
+
+    let churn = smoothstep(velocity_threshold, 2.0 * velocity_threshold, abs(vertical_velocity))
+        + smoothstep(crest_threshold, 2.0 * crest_threshold, -curvature)
+        + smoothstep(fall_threshold, 2.0 * fall_threshold, length(gradient));
+    return clamp(advected * exp(-decay * dt) + churn * generation_rate * dt, 0.0, 1.0);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn suitability(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let location = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
+
+    let depth = textureLoad(height_in, location).x;
+    let moisture = textureLoad(soil_moisture_in, location).x;
+
+    let terrain_height0 = textureLoad(terrain_height_in, location).x;
+    let terrain_dim = textureDimensions(terrain_height_in);
+    let terrain_height1 = get_terrain_height(location,  1,  0, terrain_dim);
+    let terrain_height2 = get_terrain_height(location, -1,  0, terrain_dim);
+    let terrain_height3 = get_terrain_height(location,  0,  1, terrain_dim);
+    let terrain_height4 = get_terrain_height(location,  0, -1, terrain_dim);
+    let slope = length(vec2(terrain_height1 - terrain_height2, terrain_height3 - terrain_height4)) / (2.0 * uniforms.texel_size);
+
+    // plants drown in standing water, like moist but not soaked soil and can't hold on to cliffs
+    let depth_factor = 1.0 - smoothstep(0.0, 0.05, depth);
+    let moisture_factor = 1.0 / (1.0 + pow((moisture - 0.6) * 5.0, 2.0));
+    let slope_factor = 1.0 - smoothstep(0.5, 1.5, slope);
+    textureStore(suitability_out, location, vec4(depth_factor * moisture_factor * slope_factor, 0.0, 0.0, 1.0));
+}
+
+// Publishes the cells along the edges of this chunk for the neighbours' `outside` lookups.
+@compute @workgroup_size(8, 1, 1)
+fn export_edges(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let i = i32(invocation_id.x);
+    let dim = vec2<i32>(textureDimensions(height_in));
+    let edge_length = max(dim.x, dim.y);
+    if (i < dim.y) {
+        export_edge(0, edge_length, i, vec2(0, i));
+        export_edge(1, edge_length, i, vec2(dim.x - 1, i));
+    }
+    if (i < dim.x) {
+        export_edge(2, edge_length, i, vec2(i, 0));
+        export_edge(3, edge_length, i, vec2(i, dim.y - 1));
+    }
+}
+
+fn export_edge(edge: i32, edge_length: i32, i: i32, location: vec2<i32>) {
+    edges_out[edge * edge_length + i] = vec4(
+        textureLoad(height_in, location).x,
+        textureLoad(terrain_height_in, location).x,
+        textureLoad(soil_moisture_in, location).x,
+        0.0
+    );
+}
+
+// Packs what a sleeping chunk needs to resume into a single texture, the water height stays
+// in `height_in` to draw the chunk.
+@compute @workgroup_size(8, 8, 1)
+fn pack(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let location = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
+    let vel = textureLoad(velocity, location).x;
+    let soil = textureLoad(soil_moisture_in, location).x;
+    textureStore(packed, location, vec4(pack2x16float(vec2(vel, soil)), 0u, 0u, 0u));
+    // the water drops what it carries instead of keeping it around while asleep. `pack` runs
+    // for as long as the chunk is falling asleep, the sediment is cleared so it's only dropped
+    // once
+    let terrain = textureLoad(terrain_height_in, location).x + textureLoad(sediment_in, location).x;
+    textureStore(terrain_height_in, location, vec4(terrain, 0.0, 0.0, 1.0));
+    textureStore(sediment_in, location, vec4(0.0, 0.0, 0.0, 1.0));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn unpack(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let location = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
+    let values = unpack2x16float(textureLoad(packed, location).x);
+    textureStore(velocity, location, vec4(values.x, 0.0, 0.0, 1.0));
+    textureStore(soil_moisture_out, location, vec4(values.y, 0.0, 0.0, 1.0));
+    // written back onto `height_in` and `foam_in` along with the soil moisture
+    textureStore(height_out, location, textureLoad(height_in, location));
+    textureStore(foam_out, location, textureLoad(foam_in, location));
+    textureStore(sediment_out, location, vec4(0.0, 0.0, 0.0, 1.0));
+}
+
+// Finds how low and high the terrain and water surface of a tile go and how much water is on
+// it, a workgroup per tile.
+@compute @workgroup_size(8, 8, 1)
+fn tile_bounds(
+    @builtin(workgroup_id) tile: vec3<u32>,
+    @builtin(num_workgroups) tiles: vec3<u32>,
+    @builtin(local_invocation_id) local: vec3<u32>,
+    @builtin(local_invocation_index) index: u32
+) {
+    let dim = textureDimensions(height_in);
+    let size = dim / tiles.xy;
+    let first = tile.xy * size;
+
+    var low = vec2<f32>(1e30);
+    var high = vec2<f32>(-1e30);
+    // depth, wet cells, fastest vertical speed
+    var water = vec3<f32>(0.0);
+    // the far edge of the tile's mesh sits on the first texel of the next tile
+    for (var y = local.y; y <= size.y; y += 8u) {
+        for (var x = local.x; x <= size.x; x += 8u) {
+            let location = vec2<i32>(min(first + vec2(x, y), dim - 1u));
+            let terrain = textureLoad(terrain_height_in, location).x;
+            let depth = textureLoad(height_in, location).x;
+            let heights = vec2(terrain, terrain + depth);
+            low = min(low, heights);
+            high = max(high, heights);
+            // but only the tile's own texels count towards its water
+            if (x < size.x && y < size.y) {
+                water = vec3(
+                    water.x + depth,
+                    water.y + select(0.0, 1.0, depth > WET_DEPTH),
+                    max(water.z, abs(textureLoad(velocity, location).x))
+                );
+            }
+        }
+    }
+    tile_min[index] = low;
+    tile_max[index] = high;
+    tile_water[index] = water;
+    workgroupBarrier();
+
+    for (var stride = 32u; stride > 0u; stride = stride / 2u) {
+        if (index < stride) {
+            tile_min[index] = min(tile_min[index], tile_min[index + stride]);
+            tile_max[index] = max(tile_max[index], tile_max[index + stride]);
+            let other = tile_water[index + stride];
+            tile_water[index] = vec3(
+                tile_water[index].xy + other.xy,
+                max(tile_water[index].z, other.z)
+            );
+        }
+        workgroupBarrier();
+    }
+
+    if (index == 0u) {
+        let i = 2u * (tile.y * tiles.x + tile.x);
+        tile_bounds_out[i] = vec4(tile_min[0].x, tile_max[0].x, tile_min[0].y, tile_max[0].y);
+        tile_bounds_out[i + 1u] = vec4(tile_water[0], 0.0);
+    }
+}
+
+// Smooth normals of the terrain and of the water surface on top of it, for the materials to
+// light the chunk with.
+@compute @workgroup_size(8, 8, 1)
+fn normals(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let location = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
+    let dim = textureDimensions(height_in);
+    let terrain_dim = textureDimensions(terrain_height_in);
+
+    // +x, -x, +z, -z
+    let terrain = vec4(
+        get_terrain_height(location,  1,  0, terrain_dim),
+        get_terrain_height(location, -1,  0, terrain_dim),
+        get_terrain_height(location,  0,  1, terrain_dim),
+        get_terrain_height(location,  0, -1, terrain_dim)
+    );
+    let water = vec4(
+        get_height(location,  1,  0, dim),
+        get_height(location, -1,  0, dim),
+        get_height(location,  0,  1, dim),
+        get_height(location,  0, -1, dim)
+    );
+    textureStore(terrain_normal_out, location, vec4(height_field_normal(terrain), 0.0));
+    textureStore(water_normal_out, location, vec4(height_field_normal(terrain + water), 0.0));
+}
+
+// The normal of a height field from its heights one texel towards +x, -x, +z and -z.
+fn height_field_normal(heights: vec4<f32>) -> vec3<f32> {
+    let spacing = 2.0 * uniforms.texel_size;
+    return normalize(vec3((heights.y - heights.x) / spacing, 1.0, (heights.w - heights.z) / spacing));
+}
+
+// Where light falling straight down through the water surface above `location` lands on the
+// terrain, relative to the cell.
+fn caustic_offset(location: vec2<i32>, dim: vec2<u32>) -> vec2<f32> {
+    let p = clamp(location, vec2<i32>(0), vec2<i32>(dim) - 1);
+    let ray = refract(vec3(0.0, -1.0, 0.0), height_field_normal(surface_around(p, dim)), 1.0 / 1.33);
+    return ray.xz / max(-ray.y, 0.01) * textureLoad(height_in, p).x;
+}
+
+// Caustics on the terrain under water. The refracted light of a patch of surface spreads over
+// the area the Jacobian of `caustic_offset` maps it to, so the light gets brighter where that
+// area shrinks.
+@compute @workgroup_size(8, 8, 1)
+fn caustics(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let location = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
+    let dim = textureDimensions(height_in);
+    let spacing = 2.0 * uniforms.texel_size;
+
+    let dx = (caustic_offset(location + vec2<i32>(1, 0), dim) - caustic_offset(location - vec2<i32>(1, 0), dim)) / spacing;
+    let dz = (caustic_offset(location + vec2<i32>(0, 1), dim) - caustic_offset(location - vec2<i32>(0, 1), dim)) / spacing;
+    let area = abs((1.0 + dx.x) * (1.0 + dz.y) - dx.y * dz.x);
+
+    let depth = textureLoad(height_in, location).x;
+    let intensity = select(1.0, min(1.0 / max(area, 0.001), 8.0), depth > 0.001);
+    textureStore(caustics_out, location, vec4(intensity, 0.0, 0.0, 1.0));
+}
+
+// The value of the field picked by `debug_field`, in the order of `DebugField`.
+fn debug_value(location: vec2<i32>) -> f32 {
+    switch uniforms.debug_field {
+        case 0u: { return textureLoad(height_in, location).x; }
+        case 1u: { return textureLoad(velocity, location).x; }
+        case 2u: { return textureLoad(terrain_height_in, location).x; }
+        case 3u: { return textureLoad(height_in, location).x + textureLoad(terrain_height_in, location).x; }
+        case 4u: { return textureLoad(soil_moisture_in, location).x; }
+        case 5u: { return textureLoad(foam_in, location).x; }
+        case 6u: { return textureLoad(sediment_in, location).x; }
+        case 7u: { return textureLoad(wetness, location).x; }
+        case 8u: { return textureLoad(erosion, location).x; }
+        default: { return 0.0; }
+    }
+}
+
+// Maps the field picked by `debug_field` from `debug_min`..`debug_max` onto the colour ramp
+// of `HEATMAP`.
+@compute @workgroup_size(8, 8, 1)
+fn debug_view(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let location = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
+    var ramp = array<vec3<f32>, 5>(
+        vec3(0.05, 0.03, 0.45),
+        vec3(0.0, 0.55, 0.95),
+        vec3(0.2, 0.85, 0.3),
+        vec3(0.98, 0.85, 0.1),
+        vec3(0.85, 0.1, 0.05)
+    );
+
+    let range = max(uniforms.debug_max - uniforms.debug_min, 0.000001);
+    let t = clamp((debug_value(location) - uniforms.debug_min) / range, 0.0, 1.0);
+    let position = t * 4.0;
+    let index = min(u32(position), 3u);
+    let colour = mix(ramp[index], ramp[index + 1u], position - f32(index));
+    textureStore(debug_out, location, vec4(colour, 1.0));
+}
@@ -0,0 +1,57 @@
+#import bevy_pbr::mesh_functions  mesh_position_local_to_clip
+#import bevy_pbr::mesh_bindings   mesh
+
+// the focus chunk, whose terrain is bound
+struct FluidDomain {
+    // world position of the corner at uv (0, 0)
+    min: vec2<f32>,
+    extent: vec2<f32>,
+};
+
+@group(2) @binding(0)
+var terrain_height: texture_2d<f32>;
+@group(2) @binding(1)
+var<uniform> domain: FluidDomain;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(4) color: vec4<f32>,
+
+    @location(8) i_pos_scale: vec4<f32>,
+    @location(9) i_color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) normal: vec3<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    // look up the ground here so the plants don't need the height read back every frame
+    let uv = (vertex.i_pos_scale.xz - domain.min) / domain.extent;
+    var ground = 0.0;
+    if (all(uv >= vec2(0.0)) && all(uv < vec2(1.0))) {
+        let dim = textureDimensions(terrain_height);
+        ground = textureLoad(terrain_height, vec2<i32>(uv * vec2<f32>(dim)), 0).x;
+    }
+
+    let position = vertex.position * vertex.i_pos_scale.w + vertex.i_pos_scale.xyz + vec3(0.0, ground, 0.0);
+    var out: VertexOutput;
+    out.clip_position = mesh_position_local_to_clip(
+        mesh.model,
+        vec4<f32>(position, 1.0)
+    );
+    out.color = vertex.color * vertex.i_color;
+    out.normal = vertex.normal;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let light = normalize(vec3(4.0, 8.0, 4.0));
+    let diffuse = max(dot(normalize(in.normal), light), 0.0);
+    return vec4(in.color.rgb * (0.4 + 0.6 * diffuse), 1.0);
+}
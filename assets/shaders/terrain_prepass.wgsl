@@ -0,0 +1,50 @@
+#import bevy_pbr::prepass_bindings
+#import bevy_pbr::mesh_functions as mesh_functions
+#import bevy_pbr::mesh_bindings            mesh
+
+// Displaces the terrain for the depth prepass and the shadow maps the same way
+// terrain_vertex_and_fragment.wgsl does, the inputs and outputs follow bevy's prepass.wgsl.
+
+@group(1) @binding(14)
+var height_texture: texture_2d<f32>;
+@group(1) @binding(16)
+var normal_texture: texture_2d<f32>;
+@group(1) @binding(17)
+var normal_sampler: sampler;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+#ifdef NORMAL_PREPASS
+    @location(1) world_normal: vec3<f32>,
+#endif
+#ifdef DEPTH_CLAMP_ORTHO
+    @location(5) clip_position_unclamped: vec4<f32>,
+#endif
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.uv = vertex.uv;
+    let dim = textureDimensions(height_texture);
+    // uv 1 on the east and north edges reads the last texel, not one past it
+    let texel = min(vec2<i32>(vertex.uv * vec2<f32>(dim)), vec2<i32>(dim) - 1);
+    let height = textureLoad(height_texture, texel, 0).x;
+    let position = vertex.position + vec3(0.0, height, 0.0);
+    out.clip_position = mesh_functions::mesh_position_local_to_clip(mesh.model, vec4<f32>(position, 1.0));
+#ifdef DEPTH_CLAMP_ORTHO
+    out.clip_position_unclamped = out.clip_position;
+    out.clip_position.z = min(out.clip_position.z, 1.0);
+#endif
+#ifdef NORMAL_PREPASS
+    let normal = textureSampleLevel(normal_texture, normal_sampler, vertex.uv, 0.0).xyz;
+    out.world_normal = mesh_functions::mesh_normal_local_to_world(normal);
+#endif
+    return out;
+}
@@ -0,0 +1,146 @@
+#import bevy_pbr::mesh_functions as mesh_functions
+#import bevy_pbr::mesh_bindings            mesh
+#import bevy_pbr::mesh_vertex_output       MeshVertexOutput
+#import bevy_pbr::mesh_view_bindings       view, fog
+#import bevy_pbr::mesh_view_types          FOG_MODE_OFF
+#import bevy_pbr::pbr_functions as pbr_functions
+#import bevy_core_pipeline::tonemapping    tone_mapping
+
+struct TerrainMaterial {
+    grass: vec4<f32>,
+    rock: vec4<f32>,
+    sand: vec4<f32>,
+    mud: vec4<f32>,
+    wet_darkening: f32,
+    wet_roughness: f32,
+    sand_height: f32,
+    rock_slope: f32,
+    mud_depth: f32,
+    transition: f32,
+    caustics_strength: f32,
+    roughness: f32,
+    reflectance: f32,
+};
+
+// starting at 13, clear of the StandardMaterial bindings pbr_functions brings along
+@group(1) @binding(13)
+var<uniform> material: TerrainMaterial;
+@group(1) @binding(14)
+var height_texture: texture_2d<f32>;
+@group(1) @binding(15)
+var height_sampler: sampler;
+@group(1) @binding(16)
+var normal_texture: texture_2d<f32>;
+@group(1) @binding(17)
+var normal_sampler: sampler;
+#ifdef TERRAIN_MUD
+@group(1) @binding(18)
+var water_texture: texture_2d<f32>;
+@group(1) @binding(19)
+var water_sampler: sampler;
+#endif
+#ifdef TERRAIN_CAUSTICS
+@group(1) @binding(20)
+var caustics_texture: texture_2d<f32>;
+@group(1) @binding(21)
+var caustics_sampler: sampler;
+#endif
+#ifdef TERRAIN_WETNESS
+@group(1) @binding(22)
+var wetness_texture: texture_2d<f32>;
+@group(1) @binding(23)
+var wetness_sampler: sampler;
+#endif
+#ifdef TERRAIN_DEBUG
+@group(1) @binding(24)
+var debug_texture: texture_2d<f32>;
+@group(1) @binding(25)
+var debug_sampler: sampler;
+#endif
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+};
+
+// The texel under `uv`. The east and north edges of a chunk sit at uv 1, which reads the last
+// texel rather than one past it.
+fn load_texel(texture: texture_2d<f32>, uv: vec2<f32>) -> f32 {
+    let dim = textureDimensions(texture);
+    let texel = min(vec2<i32>(uv * vec2<f32>(dim)), vec2<i32>(dim) - 1);
+    return textureLoad(texture, texel, 0).x;
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> MeshVertexOutput {
+    var out: MeshVertexOutput;
+    out.uv = vertex.uv;
+    let position = vertex.position + vec3(0.0, load_texel(height_texture, vertex.uv), 0.0);
+    out.world_position = mesh_functions::mesh_position_local_to_world(mesh.model, vec4<f32>(position, 1.0));
+    out.position = mesh_functions::mesh_position_world_to_clip(out.world_position);
+    let normal = textureSampleLevel(normal_texture, normal_sampler, vertex.uv, 0.0).xyz;
+    out.world_normal = mesh_functions::mesh_normal_local_to_world(normal);
+    return out;
+}
+
+@fragment
+fn fragment(in: MeshVertexOutput) -> @location(0) vec4<f32> {
+    let normal = normalize(textureSample(normal_texture, normal_sampler, in.uv).xyz);
+    let height = load_texel(height_texture, in.uv);
+    // tangent of the angle to the horizontal
+    let slope = length(normal.xz) / max(normal.y, 0.001);
+
+    let half_transition = material.transition * 0.5;
+    var color = mix(
+        material.sand,
+        material.grass,
+        smoothstep(material.sand_height - half_transition, material.sand_height + half_transition, height)
+    );
+    color = mix(
+        color,
+        material.rock,
+        smoothstep(material.rock_slope - half_transition, material.rock_slope + half_transition, slope)
+    );
+#ifdef TERRAIN_MUD
+    let water = textureSample(water_texture, water_sampler, in.uv).x;
+    color = mix(color, material.mud, smoothstep(0.0, material.mud_depth, water));
+#endif
+    var roughness = material.roughness;
+#ifdef TERRAIN_WETNESS
+    // damp ground is darker and glossier
+    let wetness = textureSample(wetness_texture, wetness_sampler, in.uv).x;
+    color = vec4(color.rgb * mix(1.0, material.wet_darkening, wetness), color.a);
+    roughness = mix(roughness, material.wet_roughness, wetness);
+#endif
+#ifdef TERRAIN_CAUSTICS
+    // averages out to 1 and stays there where the ground is dry
+    let caustics = textureSample(caustics_texture, caustics_sampler, in.uv).x;
+    color = vec4(color.rgb * mix(1.0, caustics, material.caustics_strength), color.a);
+#endif
+#ifdef TERRAIN_DEBUG
+    // still lit, so the shape of the terrain shows through
+    color = textureSample(debug_texture, debug_sampler, in.uv);
+#endif
+
+    var pbr_input = pbr_functions::pbr_input_new();
+    pbr_input.material.base_color = color;
+    pbr_input.material.perceptual_roughness = roughness;
+    pbr_input.material.reflectance = material.reflectance;
+    pbr_input.frag_coord = in.position;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = normal;
+    pbr_input.N = normal;
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.V = pbr_functions::calculate_view(in.world_position, pbr_input.is_orthographic);
+    pbr_input.flags = mesh.flags;
+    var output_color = pbr_functions::pbr(pbr_input);
+
+    if (fog.mode != FOG_MODE_OFF) {
+        output_color = pbr_functions::apply_fog(fog, output_color, in.world_position.xyz, view.world_position.xyz);
+    }
+#ifdef TONEMAP_IN_SHADER
+    output_color = tone_mapping(output_color, view.color_grading);
+#endif
+    return output_color;
+}
@@ -0,0 +1,402 @@
+//! False colour views of the simulation textures, for seeing what the water does below its
+//! surface.
+//!
+//! The `debug_view` pass maps the field picked in [`DebugView`] onto a colour ramp for every
+//! simulated chunk. The result is drawn over the terrain, as a minimap of the focus chunk or
+//! both, with a legend of the range the ramp covers.
+
+use bevy::{
+    prelude::*,
+    render::extract_resource::{ExtractResource, ExtractResourcePlugin},
+};
+
+use crate::{
+    extract_heights::GenderfluidImage, fluid_chunks::FluidChunk, terrain_material::TerrainMaterial,
+};
+
+pub struct DebugViewPlugin;
+
+impl Plugin for DebugViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<DebugView>()
+            .init_resource::<DebugView>()
+            .add_plugins(ExtractResourcePlugin::<DebugView>::default())
+            .add_systems(Startup, spawn_debug_view_ui)
+            .add_systems(
+                Update,
+                (
+                    control_debug_view,
+                    show_debug_view_on_terrain,
+                    update_minimap,
+                    update_legend,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Colour ramp from the bottom to the top of the range, linear RGB. The `debug_view` pass in
+/// the compute shader uses the same stops.
+pub const HEATMAP: [[f32; 3]; 5] = [
+    [0.05, 0.03, 0.45],
+    [0.0, 0.55, 0.95],
+    [0.2, 0.85, 0.3],
+    [0.98, 0.85, 0.1],
+    [0.85, 0.1, 0.05],
+];
+
+/// The colour of `t` in `0..=1` on [`HEATMAP`].
+pub fn heatmap(t: f32) -> Color {
+    let position = t.clamp(0.0, 1.0) * (HEATMAP.len() - 1) as f32;
+    let index = (position as usize).min(HEATMAP.len() - 2);
+    let [r, g, b] = std::array::from_fn(|channel| {
+        let (low, high) = (HEATMAP[index][channel], HEATMAP[index + 1][channel]);
+        low + (high - low) * (position - index as f32)
+    });
+    Color::rgb_linear(r, g, b)
+}
+
+#[derive(Resource, ExtractResource, Reflect, Clone, Debug)]
+#[reflect(Resource)]
+pub struct DebugView {
+    pub enabled: bool,
+    pub field: DebugField,
+    pub display: DebugDisplay,
+    /// Values mapped to the bottom and the top of the ramp, per [`DebugField`].
+    pub ranges: [Vec2; DebugField::ALL.len()],
+}
+
+impl DebugView {
+    /// Turns the debug view on and off.
+    pub const TOGGLE_KEY: KeyCode = KeyCode::F1;
+    /// Moves on to the next field, or to the next display while shift is held.
+    pub const CYCLE_KEY: KeyCode = KeyCode::F2;
+
+    pub fn range(&self) -> Vec2 {
+        self.ranges[self.field as usize]
+    }
+}
+
+impl Default for DebugView {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            field: DebugField::WaterDepth,
+            display: DebugDisplay::Both,
+            ranges: DebugField::ALL.map(DebugField::default_range),
+        }
+    }
+}
+
+/// A simulation texture, or a value derived from them, the debug view can show. The order
+/// matches the `debug_view` pass.
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DebugField {
+    #[default]
+    WaterDepth,
+    /// Vertical speed of the water surface.
+    Velocity,
+    TerrainHeight,
+    /// Terrain height plus water depth.
+    SurfaceHeight,
+    SoilMoisture,
+    Foam,
+    Sediment,
+    Wetness,
+    /// Change to the terrain height by erosion in the last step.
+    Erosion,
+}
+
+impl DebugField {
+    pub const ALL: [DebugField; 9] = [
+        DebugField::WaterDepth,
+        DebugField::Velocity,
+        DebugField::TerrainHeight,
+        DebugField::SurfaceHeight,
+        DebugField::SoilMoisture,
+        DebugField::Foam,
+        DebugField::Sediment,
+        DebugField::Wetness,
+        DebugField::Erosion,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DebugField::WaterDepth => "water depth",
+            DebugField::Velocity => "velocity",
+            DebugField::TerrainHeight => "terrain height",
+            DebugField::SurfaceHeight => "surface height",
+            DebugField::SoilMoisture => "soil moisture",
+            DebugField::Foam => "foam",
+            DebugField::Sediment => "sediment",
+            DebugField::Wetness => "wetness",
+            DebugField::Erosion => "erosion",
+        }
+    }
+
+    pub fn default_range(self) -> Vec2 {
+        match self {
+            DebugField::WaterDepth => Vec2::new(0.0, 0.5),
+            DebugField::Velocity => Vec2::new(-1.0, 1.0),
+            DebugField::TerrainHeight | DebugField::SurfaceHeight => Vec2::new(0.0, 3.0),
+            DebugField::SoilMoisture | DebugField::Foam | DebugField::Wetness => {
+                Vec2::new(0.0, 1.0)
+            }
+            DebugField::Sediment => Vec2::new(0.0, 0.05),
+            DebugField::Erosion => Vec2::new(-0.001, 0.001),
+        }
+    }
+
+    fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// Where the debug view is drawn.
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DebugDisplay {
+    /// Over the terrain, lit like it.
+    Terrain,
+    /// On a minimap of the focus chunk.
+    Minimap,
+    #[default]
+    Both,
+}
+
+impl DebugDisplay {
+    fn next(self) -> Self {
+        match self {
+            DebugDisplay::Terrain => DebugDisplay::Minimap,
+            DebugDisplay::Minimap => DebugDisplay::Both,
+            DebugDisplay::Both => DebugDisplay::Terrain,
+        }
+    }
+
+    fn terrain(self) -> bool {
+        self != DebugDisplay::Minimap
+    }
+
+    fn minimap(self) -> bool {
+        self != DebugDisplay::Terrain
+    }
+}
+
+fn control_debug_view(keyboard: Res<Input<KeyCode>>, mut view: ResMut<DebugView>) {
+    if keyboard.just_pressed(DebugView::TOGGLE_KEY) {
+        view.enabled = !view.enabled;
+    }
+    if keyboard.just_pressed(DebugView::CYCLE_KEY) {
+        if keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+            view.display = view.display.next();
+        } else {
+            view.field = view.field.next();
+        }
+    }
+}
+
+fn show_debug_view_on_terrain(
+    view: Res<DebugView>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+) {
+    if !view.is_changed() {
+        return;
+    }
+    let show = view.enabled && view.display.terrain();
+    for (_, material) in materials.iter_mut() {
+        if material.show_debug != show {
+            material.show_debug = show;
+        }
+    }
+}
+
+#[derive(Component)]
+struct Minimap;
+
+#[derive(Component)]
+struct Legend;
+
+#[derive(Component)]
+struct LegendTitle;
+
+/// Labels of the bottom, middle and top of the range.
+#[derive(Component)]
+struct LegendLabel(f32);
+
+const LEGEND_SWATCHES: usize = 16;
+
+fn spawn_debug_view_ui(mut commands: Commands) {
+    let text_style = TextStyle {
+        font_size: 14.0,
+        color: Color::WHITE,
+        ..default()
+    };
+
+    commands.spawn((
+        ImageBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                right: Val::Px(8.0),
+                bottom: Val::Px(8.0),
+                width: Val::Px(256.0),
+                height: Val::Px(256.0),
+                ..default()
+            },
+            ..default()
+        },
+        Minimap,
+    ));
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(8.0),
+                    bottom: Val::Px(8.0),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(4.0),
+                    padding: UiRect::all(Val::Px(6.0)),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                ..default()
+            },
+            Legend,
+        ))
+        .with_children(|legend| {
+            legend.spawn((
+                TextBundle::from_section("", text_style.clone()),
+                LegendTitle,
+            ));
+            legend
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|ramp| {
+                    for swatch in 0..LEGEND_SWATCHES {
+                        let t = (swatch as f32 + 0.5) / LEGEND_SWATCHES as f32;
+                        ramp.spawn(NodeBundle {
+                            style: Style {
+                                width: Val::Px(12.0),
+                                height: Val::Px(12.0),
+                                ..default()
+                            },
+                            background_color: heatmap(t).into(),
+                            ..default()
+                        });
+                    }
+                });
+            legend
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        justify_content: JustifyContent::SpaceBetween,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|labels| {
+                    for t in [0.0, 0.5, 1.0] {
+                        labels.spawn((
+                            TextBundle::from_section("", text_style.clone()),
+                            LegendLabel(t),
+                        ));
+                    }
+                });
+        });
+}
+
+/// Points the minimap at the debug image of the focus chunk.
+fn update_minimap(
+    view: Res<DebugView>,
+    genderfluid_image: Res<GenderfluidImage>,
+    chunks: Query<&FluidChunk>,
+    mut minimaps: Query<(&mut Style, &mut UiImage), With<Minimap>>,
+) {
+    let focus = chunks
+        .iter()
+        .find(|chunk| chunk.coord == genderfluid_image.focus);
+    for (mut style, mut image) in &mut minimaps {
+        let display = match focus {
+            Some(focus) if view.enabled && view.display.minimap() => {
+                if image.texture != focus.debug {
+                    image.texture = focus.debug.clone();
+                }
+                Display::Flex
+            }
+            _ => Display::None,
+        };
+        if style.display != display {
+            style.display = display;
+        }
+    }
+}
+
+fn update_legend(
+    view: Res<DebugView>,
+    mut legends: Query<&mut Style, With<Legend>>,
+    mut titles: Query<&mut Text, (With<LegendTitle>, Without<LegendLabel>)>,
+    mut labels: Query<(&mut Text, &LegendLabel), Without<LegendTitle>>,
+) {
+    if !view.is_changed() {
+        return;
+    }
+    for mut style in &mut legends {
+        style.display = if view.enabled {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+    for mut title in &mut titles {
+        title.sections[0].value = format!(
+            "{} ({:?} to toggle, {:?} to cycle)",
+            view.field.name(),
+            DebugView::TOGGLE_KEY,
+            DebugView::CYCLE_KEY
+        );
+    }
+    let range = view.range();
+    for (mut text, LegendLabel(t)) in &mut labels {
+        text.sections[0].value = format!("{:.3}", range.x + (range.y - range.x) * t);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_cycle_through_all() {
+        for (i, field) in DebugField::ALL.into_iter().enumerate() {
+            assert_eq!(
+                field.next(),
+                DebugField::ALL[(i + 1) % DebugField::ALL.len()]
+            );
+        }
+        assert_eq!(DebugField::Erosion.next(), DebugField::WaterDepth);
+    }
+
+    #[test]
+    fn heatmap_hits_the_stops() {
+        let stops = HEATMAP.len() - 1;
+        for (i, stop) in HEATMAP.into_iter().enumerate() {
+            let [r, g, b, _] = heatmap(i as f32 / stops as f32).as_linear_rgba_f32();
+            for (channel, expected) in [r, g, b].into_iter().zip(stop) {
+                assert!((channel - expected).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn heatmap_clamps_outside_the_range() {
+        assert_eq!(heatmap(-1.0), heatmap(0.0));
+        assert_eq!(heatmap(2.0), heatmap(1.0));
+    }
+}
@@ -1,228 +1,393 @@
-use bevy::{
-    prelude::*,
-    reflect::TypeUuid,
-    render::{
-        extract_resource::ExtractResource,
-        render_asset::RenderAssets,
-        render_graph::{self},
-        render_resource::*,
-        renderer::{RenderContext, RenderDevice},
-    },
-};
-use bytemuck::{Pod, Zeroable};
-use std::{borrow::Cow, num::NonZeroU32};
-
-use crate::{SIZE, WORKGROUP_SIZE, CELL_SIZE};
-
-#[derive(Resource, Clone, ExtractResource)]
-pub struct GenderfluidImage {
-    pub height1: Handle<Image>,
-    pub height2: Handle<Image>,
-    pub velocity: Handle<Image>,
-    pub terrain_height: Handle<Image>,
-    pub uniforms: Buffer,
-	pub extract_positions: Buffer,
-	pub extract_height: Buffer,
-	pub extract_height_mapped: Buffer,
-	pub extract_terrain_height: Buffer,
-	pub extract_terrain_height_mapped: Buffer,
-}
-
-#[derive(Resource)]
-pub struct GenderfluidExtractImageBindGroup(pub BindGroup);
-
-pub fn queue_extract_bind_group(
-    mut commands: Commands,
-    pipeline: Res<GenderfluidExtractPipeline>,
-    gpu_images: Res<RenderAssets<Image>>,
-    genderfluid_image: Res<GenderfluidImage>,
-    render_device: Res<RenderDevice>,
-) {
-    let height1 = &gpu_images[&genderfluid_image.height1];
-    // let height2 = &gpu_images[&genderfluid_image.height2];
-    // let velocity = &gpu_images[&genderfluid_image.velocity];
-    let terrain_height = &gpu_images[&genderfluid_image.terrain_height];
-
-    let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
-        label: None,
-        layout: &pipeline.texture_bind_group_layout,
-        entries: &[
-            BindGroupEntry {
-                binding: 0,
-                resource: BindingResource::TextureView(&height1.texture_view),
-            },
-            // BindGroupEntry {
-            //     binding: 1,
-            //     resource: BindingResource::TextureView(&height2.texture_view),
-            // },
-            // BindGroupEntry {
-            //     binding: 2,
-            //     resource: BindingResource::TextureView(&velocity.texture_view),
-            // },
-            BindGroupEntry {
-                binding: 3,
-                resource: BindingResource::TextureView(&terrain_height.texture_view),
-            },
-            // BindGroupEntry {
-            //     binding: 4,
-            //     resource: genderfluid_image.uniforms.as_entire_binding(),
-            // },
-            BindGroupEntry {
-                binding: 5,
-                resource: genderfluid_image.extract_positions.as_entire_binding(),
-            },
-            BindGroupEntry {
-                binding: 6,
-                resource: genderfluid_image.extract_height.as_entire_binding(),
-            },
-            BindGroupEntry {
-                binding: 7,
-                resource: genderfluid_image.extract_terrain_height.as_entire_binding(),
-            },
-        ],
-    });
-    commands.insert_resource(GenderfluidExtractImageBindGroup(bind_group));
-}
-
-#[derive(Resource)]
-pub struct GenderfluidExtractPipeline {
-    texture_bind_group_layout: BindGroupLayout,
-    update_pipeline: CachedComputePipelineId,
-}
-
-#[derive(Resource, Reflect, Debug, Clone, TypeUuid, ShaderType, Pod, Zeroable, Copy)]
-#[repr(C)]
-#[uuid = "657741ad-e8f8-43dc-bf2b-9b79c43e38e9"]
-pub struct QueryPosition {
-    pub x: i32,
-	pub y: i32,
-}
-
-impl FromWorld for GenderfluidExtractPipeline {
-    fn from_world(world: &mut World) -> Self {
-        let make_binding = |binding: u32, access: StorageTextureAccess| BindGroupLayoutEntry {
-            binding,
-            visibility: ShaderStages::COMPUTE,
-            ty: BindingType::StorageTexture {
-                access,
-                format: TextureFormat::R32Float,
-                view_dimension: TextureViewDimension::D2,
-            },
-            count: None,
-        };
-        let make_extract_binding = |binding: u32, ro: bool, size: usize| BindGroupLayoutEntry {
-            binding,
-            visibility: ShaderStages::COMPUTE,
-            ty: BindingType::Buffer {
-                ty: BufferBindingType::Storage { read_only: ro },
-                has_dynamic_offset: false,
-                min_binding_size: BufferSize::new(size as u64),
-            },
-            count: Some(NonZeroU32::new((SIZE / CELL_SIZE) * (SIZE / CELL_SIZE)).unwrap()),
-        };
-        let texture_bind_group_layout =
-            world
-                .resource::<RenderDevice>()
-                .create_bind_group_layout(&BindGroupLayoutDescriptor {
-                    label: None,
-                    entries: &[
-                        // height_in
-                        make_binding(0, StorageTextureAccess::ReadOnly),
-                        // velocity
-                        // make_binding(2, StorageTextureAccess::ReadOnly),
-                        // terrain_height_in
-                        make_binding(3, StorageTextureAccess::ReadOnly),
-                        // extract_positions
-                        make_extract_binding(5, true, std::mem::size_of::<QueryPosition>()),
-                        // extract_height_out
-                        make_extract_binding(6, false, std::mem::size_of::<f32>()),
-                        // extract_terrain_height_out
-                        make_extract_binding(7, false, std::mem::size_of::<f32>()),
-                    ],
-                });
-        let shader = world
-            .resource::<AssetServer>()
-            .load("shaders/height_map_extract_compute.wgsl");
-        let pipeline_cache = world.resource::<PipelineCache>();
-        let update_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
-            label: None,
-            layout: vec![texture_bind_group_layout.clone()],
-            push_constant_ranges: Vec::new(),
-            shader,
-            shader_defs: vec![],
-            entry_point: Cow::from("extract"),
-        });
-
-        GenderfluidExtractPipeline {
-            texture_bind_group_layout,
-            update_pipeline,
-        }
-    }
-}
-
-enum GenderfluidState {
-    Loading,
-    Update,
-}
-
-pub struct GenderfluidExtractNode {
-    state: GenderfluidState,
-}
-
-impl Default for GenderfluidExtractNode {
-    fn default() -> Self {
-        Self {
-            state: GenderfluidState::Loading,
-        }
-    }
-}
-
-impl render_graph::Node for GenderfluidExtractNode {
-    fn update(&mut self, world: &mut World) {
-        let pipeline = world.resource::<GenderfluidExtractPipeline>();
-        let pipeline_cache = world.resource::<PipelineCache>();
-
-        // if the corresponding pipeline has loaded, transition to the next stage
-        match self.state {
-            GenderfluidState::Loading => {
-                if let CachedPipelineState::Ok(_) =
-                    pipeline_cache.get_compute_pipeline_state(pipeline.update_pipeline)
-                {
-                    self.state = GenderfluidState::Update;
-                }
-            }
-            GenderfluidState::Update => {}
-        }
-    }
-
-    fn run(
-        &self,
-        _graph: &mut render_graph::RenderGraphContext,
-        render_context: &mut RenderContext,
-        world: &World,
-    ) -> Result<(), render_graph::NodeRunError> {
-        let texture_bind_group = &world.resource::<GenderfluidExtractImageBindGroup>().0;
-        let pipeline_cache = world.resource::<PipelineCache>();
-        let pipeline = world.resource::<GenderfluidExtractPipeline>();
-
-        let mut pass = render_context
-            .command_encoder()
-            .begin_compute_pass(&ComputePassDescriptor::default());
-
-        pass.set_bind_group(0, texture_bind_group, &[]);
-
-        // select the pipeline based on the current state
-        match self.state {
-            GenderfluidState::Loading => {}
-            GenderfluidState::Update => {
-                let update_pipeline = pipeline_cache
-                    .get_compute_pipeline(pipeline.update_pipeline)
-                    .unwrap();
-                pass.set_pipeline(update_pipeline);
-                pass.dispatch_workgroups(SIZE / WORKGROUP_SIZE, 1, 1);
-            }
-        }
-
-        Ok(())
-    }
-}
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        extract_resource::ExtractResource,
+        render_asset::RenderAssets,
+        render_graph::{self},
+        render_resource::*,
+        renderer::{RenderContext, RenderDevice},
+    },
+};
+use bytemuck::{Pod, Zeroable};
+use std::{borrow::Cow, num::NonZeroU32};
+
+use crate::{
+    fluid_chunks::FluidChunk,
+    fluid_diagnostics::{PassTimestamps, TimedPass},
+    fluid_domain::FluidDomain,
+    SIZE, WORKGROUP_SIZE, CELL_SIZE,
+};
+
+/// The chunk the player is in. The plants in it are drawn against its terrain texture and the
+/// minimap shows it.
+#[derive(Resource, Clone, ExtractResource)]
+pub struct GenderfluidImage {
+    pub focus: UVec2,
+    pub focus_domain: FluidDomain,
+    pub terrain_height: Handle<Image>,
+}
+
+/// What the plants of a chunk read back from its cells and draw out of them.
+///
+/// The `extract` pass looks up one texel per plant cell of the chunk, the plant's or a spot a
+/// new one could grow at, followed by the player's.
+#[derive(Clone)]
+pub struct ChunkPlants {
+    pub positions: Buffer,
+    /// Water height, terrain height, soil moisture and suitability at the positions.
+    pub extracted: [Buffer; 4],
+    /// Where [`ChunkPlants::extracted`] is copied to be read back.
+    pub extracted_mapped: [Buffer; 4],
+    /// How much water the plants draw from each texel (`r`) and how much they shade it (`g`),
+    /// written from the CPU every frame.
+    pub water: Texture,
+    pub water_view: TextureView,
+}
+
+impl ChunkPlants {
+    pub fn new(render_device: &RenderDevice, domain: &FluidDomain) -> Self {
+        let queries = Self::queries(domain) as u64;
+        let extracted = |label| {
+            render_device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: std::mem::size_of::<f32>() as u64 * queries,
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        };
+        let mapped = |label| {
+            render_device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: std::mem::size_of::<f32>() as u64 * queries,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            })
+        };
+        // written from the CPU every frame, so it doesn't go through the image assets
+        let water = render_device.create_texture(&TextureDescriptor {
+            label: Some("fluid plant water"),
+            size: Extent3d {
+                width: domain.resolution.x,
+                height: domain.resolution.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rg32Float,
+            usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        Self {
+            positions: render_device.create_buffer(&BufferDescriptor {
+                label: Some("fluid extract positions"),
+                size: std::mem::size_of::<QueryPosition>() as u64 * queries,
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            extracted: [
+                extracted("fluid extract height"),
+                extracted("fluid extract terrain height"),
+                extracted("fluid extract soil moisture"),
+                extracted("fluid extract suitability"),
+            ],
+            extracted_mapped: [
+                mapped("fluid extract height mapped"),
+                mapped("fluid extract terrain height mapped"),
+                mapped("fluid extract soil moisture mapped"),
+                mapped("fluid extract suitability mapped"),
+            ],
+            water_view: water.create_view(&TextureViewDescriptor::default()),
+            water,
+        }
+    }
+
+    /// Number of texels looked up in a chunk covering `domain`, the player's is the last one.
+    pub fn queries(domain: &FluidDomain) -> usize {
+        let cells = domain.cell_count();
+        (cells.x * cells.y) as usize + 1
+    }
+}
+
+/// The extract bind group of every chunk with working textures.
+#[derive(Resource)]
+pub struct GenderfluidExtractImageBindGroups(pub Vec<BindGroup>);
+
+pub fn queue_extract_bind_group(
+    mut commands: Commands,
+    pipeline: Res<GenderfluidExtractPipeline>,
+    gpu_images: Res<RenderAssets<Image>>,
+    chunks: Query<&FluidChunk>,
+    render_device: Res<RenderDevice>,
+) {
+    let mut bind_groups = vec![];
+    for chunk in &chunks {
+        let Some(working_set) = &chunk.working_set else {
+            continue;
+        };
+        // textures of a chunk that just woke up take a frame to be uploaded, skip the
+        // extraction until they are
+        let (Some(height), Some(terrain_height), Some(soil_moisture), Some(suitability)) = (
+            gpu_images.get(&chunk.height),
+            gpu_images.get(&chunk.terrain_height),
+            gpu_images.get(&working_set.soil_moisture),
+            gpu_images.get(&working_set.suitability),
+        ) else {
+            continue;
+        };
+        bind_groups.push(extract_bind_group(
+            &render_device,
+            &pipeline,
+            &chunk.plants,
+            [height, terrain_height, soil_moisture, suitability].map(|image| &image.texture_view),
+        ));
+    }
+    commands.insert_resource(GenderfluidExtractImageBindGroups(bind_groups));
+}
+
+/// Binds the water height, terrain height, soil moisture and suitability `textures` of a chunk
+/// to its `plants` buffers.
+fn extract_bind_group(
+    render_device: &RenderDevice,
+    pipeline: &GenderfluidExtractPipeline,
+    plants: &ChunkPlants,
+    [height, terrain_height, soil_moisture, suitability]: [&TextureView; 4],
+) -> BindGroup {
+    render_device.create_bind_group(&BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.texture_bind_group_layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(height),
+            },
+            // BindGroupEntry {
+            //     binding: 1,
+            //     resource: BindingResource::TextureView(&height2.texture_view),
+            // },
+            // BindGroupEntry {
+            //     binding: 2,
+            //     resource: BindingResource::TextureView(&velocity.texture_view),
+            // },
+            BindGroupEntry {
+                binding: 3,
+                resource: BindingResource::TextureView(terrain_height),
+            },
+            // BindGroupEntry {
+            //     binding: 4,
+            //     resource: genderfluid_image.uniforms.as_entire_binding(),
+            // },
+            BindGroupEntry {
+                binding: 5,
+                resource: plants.positions.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 6,
+                resource: plants.extracted[0].as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 7,
+                resource: plants.extracted[1].as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 8,
+                resource: BindingResource::TextureView(soil_moisture),
+            },
+            BindGroupEntry {
+                binding: 9,
+                resource: plants.extracted[2].as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 10,
+                resource: BindingResource::TextureView(suitability),
+            },
+            BindGroupEntry {
+                binding: 11,
+                resource: plants.extracted[3].as_entire_binding(),
+            },
+        ],
+    })
+}
+
+#[derive(Resource)]
+pub struct GenderfluidExtractPipeline {
+    texture_bind_group_layout: BindGroupLayout,
+    update_pipeline: CachedComputePipelineId,
+}
+
+impl GenderfluidExtractPipeline {
+    /// Every pipeline [`GenderfluidExtractNode`] dispatches, by entry point.
+    pub fn pipelines(&self) -> [(&'static str, CachedComputePipelineId); 1] {
+        [("extract", self.update_pipeline)]
+    }
+}
+
+#[derive(Resource, Reflect, Debug, Clone, TypeUuid, ShaderType, Pod, Zeroable, Copy)]
+#[repr(C)]
+#[uuid = "657741ad-e8f8-43dc-bf2b-9b79c43e38e9"]
+pub struct QueryPosition {
+    pub x: i32,
+	pub y: i32,
+}
+
+impl From<IVec2> for QueryPosition {
+    fn from(texel: IVec2) -> Self {
+        QueryPosition {
+            x: texel.x,
+            y: texel.y,
+        }
+    }
+}
+
+impl FromWorld for GenderfluidExtractPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let make_binding = |binding: u32, access: StorageTextureAccess| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::StorageTexture {
+                access,
+                format: TextureFormat::R32Float,
+                view_dimension: TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let make_extract_binding = |binding: u32, ro: bool, size: usize| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: ro },
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(size as u64),
+            },
+            count: Some(NonZeroU32::new((SIZE / CELL_SIZE) * (SIZE / CELL_SIZE)).unwrap()),
+        };
+        let texture_bind_group_layout =
+            world
+                .resource::<RenderDevice>()
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: None,
+                    entries: &[
+                        // height_in
+                        make_binding(0, StorageTextureAccess::ReadOnly),
+                        // velocity
+                        // make_binding(2, StorageTextureAccess::ReadOnly),
+                        // terrain_height_in
+                        make_binding(3, StorageTextureAccess::ReadOnly),
+                        // extract_positions
+                        make_extract_binding(5, true, std::mem::size_of::<QueryPosition>()),
+                        // extract_height_out
+                        make_extract_binding(6, false, std::mem::size_of::<f32>()),
+                        // extract_terrain_height_out
+                        make_extract_binding(7, false, std::mem::size_of::<f32>()),
+                        // soil_moisture_in
+                        make_binding(8, StorageTextureAccess::ReadOnly),
+                        // extract_soil_moisture_out
+                        make_extract_binding(9, false, std::mem::size_of::<f32>()),
+                        // suitability_in
+                        make_binding(10, StorageTextureAccess::ReadOnly),
+                        // extract_suitability_out
+                        make_extract_binding(11, false, std::mem::size_of::<f32>()),
+                    ],
+                });
+        let shader = world
+            .resource::<AssetServer>()
+            .load("shaders/height_map_extract_compute.wgsl");
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let update_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: None,
+            layout: vec![texture_bind_group_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader,
+            shader_defs: vec![],
+            entry_point: Cow::from("extract"),
+        });
+
+        GenderfluidExtractPipeline {
+            texture_bind_group_layout,
+            update_pipeline,
+        }
+    }
+}
+
+enum GenderfluidState {
+    Loading,
+    Update,
+}
+
+pub struct GenderfluidExtractNode {
+    state: GenderfluidState,
+}
+
+impl Default for GenderfluidExtractNode {
+    fn default() -> Self {
+        Self {
+            state: GenderfluidState::Loading,
+        }
+    }
+}
+
+impl render_graph::Node for GenderfluidExtractNode {
+    fn update(&mut self, world: &mut World) {
+        let pipeline = world.resource::<GenderfluidExtractPipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        // if the corresponding pipeline has loaded, transition to the next stage
+        match self.state {
+            GenderfluidState::Loading => {
+                if let CachedPipelineState::Ok(_) =
+                    pipeline_cache.get_compute_pipeline_state(pipeline.update_pipeline)
+                {
+                    self.state = GenderfluidState::Update;
+                }
+            }
+            GenderfluidState::Update => {}
+        }
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let GenderfluidState::Update = self.state else {
+            return Ok(());
+        };
+        let Some(GenderfluidExtractImageBindGroups(bind_groups)) =
+            world.get_resource::<GenderfluidExtractImageBindGroups>()
+        else {
+            return Ok(());
+        };
+        if bind_groups.is_empty() {
+            return Ok(());
+        }
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = world.resource::<GenderfluidExtractPipeline>();
+        // gone while the shader recompiles
+        let Some(update_pipeline) = pipeline_cache.get_compute_pipeline(pipeline.update_pipeline)
+        else {
+            return Ok(());
+        };
+
+        let timestamps = world.get_resource::<PassTimestamps>();
+
+        let encoder = render_context.command_encoder();
+        if let Some(timestamps) = timestamps {
+            timestamps.start(encoder, TimedPass::Extract);
+        }
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+            pass.set_pipeline(update_pipeline);
+            for bind_group in bind_groups {
+                pass.set_bind_group(0, bind_group, &[]);
+                pass.dispatch_workgroups(SIZE / WORKGROUP_SIZE, 1, 1);
+            }
+        }
+        if let Some(timestamps) = timestamps {
+            timestamps.end(encoder, TimedPass::Extract);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+//! What happens to water at the edges of the fluid domain.
+
+use bevy::prelude::*;
+
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq)]
+pub enum BoundaryCondition {
+    /// Reflects water and waves back into the domain.
+    #[default]
+    Wall,
+    /// Continues the water surface past the edge, so water and waves flow out of the domain.
+    Open,
+    /// Connects the edge to the opposite one, which is made periodic as well, see
+    /// [`FluidBoundaryConditions::sides`].
+    Periodic,
+    /// Holds the water surface just outside of the edge at a fixed world height, water flows in
+    /// wherever the surface inside is lower.
+    Inflow { level: f32 },
+}
+
+impl BoundaryCondition {
+    /// The `BOUNDARY_*` constant the compute shader knows this condition by.
+    pub fn kind(&self) -> u32 {
+        match self {
+            BoundaryCondition::Wall => 0,
+            BoundaryCondition::Open => 1,
+            BoundaryCondition::Periodic => 2,
+            BoundaryCondition::Inflow { .. } => 3,
+        }
+    }
+
+    pub fn level(&self) -> f32 {
+        match self {
+            BoundaryCondition::Inflow { level } => *level,
+            _ => 0.0,
+        }
+    }
+}
+
+/// The boundary condition of each edge of the fluid domain.
+#[derive(Resource, Reflect, Clone, Debug, Default)]
+#[reflect(Resource)]
+pub struct FluidBoundaryConditions {
+    pub west: BoundaryCondition,
+    pub east: BoundaryCondition,
+    pub south: BoundaryCondition,
+    pub north: BoundaryCondition,
+}
+
+impl FluidBoundaryConditions {
+    /// The conditions in the west, east, south, north order the chunks use for their sides.
+    ///
+    /// Periodic edges come in pairs, water leaving through one comes back in through the
+    /// opposite one. An edge opposite a periodic one is periodic as well, whatever it is set to.
+    pub fn sides(&self) -> [BoundaryCondition; 4] {
+        let mut sides = [self.west, self.east, self.south, self.north];
+        for (first, second) in [(0, 1), (2, 3)] {
+            if sides[first] == BoundaryCondition::Periodic
+                || sides[second] == BoundaryCondition::Periodic
+            {
+                sides[first] = BoundaryCondition::Periodic;
+                sides[second] = BoundaryCondition::Periodic;
+            }
+        }
+        sides
+    }
+
+    /// Whether a periodic edge is opposite one that isn't, [`FluidBoundaryConditions::sides`]
+    /// overrides the other edge then.
+    pub fn has_unpaired_periodic(&self) -> bool {
+        self.sides() != [self.west, self.east, self.south, self.north]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn periodic_edges_are_paired() {
+        let conditions = FluidBoundaryConditions {
+            west: BoundaryCondition::Periodic,
+            east: BoundaryCondition::Open,
+            south: BoundaryCondition::Wall,
+            north: BoundaryCondition::Inflow { level: 1.0 },
+        };
+        assert!(conditions.has_unpaired_periodic());
+        assert_eq!(
+            conditions.sides(),
+            [
+                BoundaryCondition::Periodic,
+                BoundaryCondition::Periodic,
+                BoundaryCondition::Wall,
+                BoundaryCondition::Inflow { level: 1.0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn paired_periodic_edges_are_kept() {
+        let conditions = FluidBoundaryConditions {
+            south: BoundaryCondition::Periodic,
+            north: BoundaryCondition::Periodic,
+            ..default()
+        };
+        assert!(!conditions.has_unpaired_periodic());
+        assert_eq!(conditions.sides()[2..], [BoundaryCondition::Periodic; 2]);
+    }
+}
@@ -0,0 +1,556 @@
+//! Splits the fluid domain into chunks that are only simulated near the player or the camera.
+//!
+//! Every chunk has its own set of simulation textures. Neighbouring awake chunks exchange the
+//! cells along their shared edges through small storage buffers every frame, so water keeps
+//! flowing across chunk borders. Far away chunks fall asleep: their velocity and soil moisture
+//! get packed into a single texture and the rest of the working textures are dropped. What's
+//! needed to draw them stays around, the water and terrain heights, the normals, the foam, the
+//! caustics, the wetness and the debug view.
+//!
+//! Plants grow, draw water and spread in every awake chunk, each reads back the cells of its own
+//! plants, see [`ChunkPlants`]. The plants of a sleeping chunk stay as they are until it wakes
+//! up again.
+//!
+//! The render world runs the compute passes a chunk's state asks for and reports back through
+//! [`FluidChunkFeedback`] once they have been submitted.
+
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    ecs::query::QueryItem,
+    input::InputSystem,
+    math::Vec3Swizzles,
+    prelude::*,
+    render::{
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        render_resource::*,
+        renderer::RenderDevice,
+        RenderApp,
+    },
+    utils::HashMap,
+};
+
+use crate::{
+    extract_heights::{ChunkPlants, GenderfluidImage},
+    fluid_boundary::{BoundaryCondition, FluidBoundaryConditions},
+    fluid_domain::FluidDomain,
+    fluid_tiles::{spawn_chunk_tiles, tile_bounds_size, ChunkWaterStats, FluidTileSettings},
+    scene_color::SceneColor,
+    terrain_generator::{TerrainGenerator, TerrainPreset},
+    terrain_material::TerrainMaterial,
+    water_material::WaterMaterial, FluidComputeUniforms, Player,
+};
+
+pub struct FluidChunkPlugin;
+
+impl Plugin for FluidChunkPlugin {
+    fn build(&self, app: &mut App) {
+        let feedback = FluidChunkFeedback::default();
+        app.add_plugins(ExtractComponentPlugin::<FluidChunk>::default())
+            .add_event::<ResetSimulation>()
+            .register_type::<FluidChunkSettings>()
+            .init_resource::<FluidChunkSettings>()
+            .register_type::<FluidBoundaryConditions>()
+            .init_resource::<FluidBoundaryConditions>()
+            .insert_resource(feedback.clone())
+            .add_systems(PreUpdate, send_reset_on_key.after(InputSystem))
+            .add_systems(
+                PostUpdate,
+                (
+                    reset_chunks,
+                    update_chunk_states,
+                    update_chunk_neighbours,
+                    track_focus_chunk,
+                )
+                    .chain(),
+            );
+        app.sub_app_mut(RenderApp).insert_resource(feedback);
+    }
+}
+
+#[derive(Resource, Reflect, Clone, Debug)]
+#[reflect(Resource)]
+pub struct FluidChunkSettings {
+    /// Number of chunks along x and z, the [`FluidDomain`] is split evenly between them.
+    pub chunks: UVec2,
+    /// Chunks closer than this to the player or a camera are simulated.
+    pub wake_distance: f32,
+}
+
+impl Default for FluidChunkSettings {
+    fn default() -> Self {
+        Self {
+            chunks: UVec2::splat(2),
+            wake_distance: 2.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkState {
+    /// Waiting for the `init` pass to generate the terrain and initial water.
+    Generating,
+    Awake,
+    /// Waiting for the `pack` pass before the working textures are dropped.
+    FallingAsleep,
+    Asleep,
+    /// Waiting for the `unpack` pass to restore the freshly created working textures.
+    WakingUp,
+}
+
+/// The textures a chunk only needs while it is simulated.
+#[derive(Clone)]
+pub struct ChunkWorkingSet {
+    /// Written by the passes and copied onto [`FluidChunk::height`] afterwards.
+    pub height_out: Handle<Image>,
+    pub velocity: Handle<Image>,
+    pub soil_moisture: Handle<Image>,
+    /// Written by the passes and copied onto `soil_moisture` afterwards.
+    pub soil_moisture_out: Handle<Image>,
+    pub suitability: Handle<Image>,
+    /// Written by the passes and copied onto [`FluidChunk::foam`] afterwards.
+    pub foam_out: Handle<Image>,
+    /// Carried by the water, dropped onto the terrain when the chunk falls asleep.
+    pub sediment: Handle<Image>,
+    /// Written by the passes and copied onto `sediment` afterwards.
+    pub sediment_out: Handle<Image>,
+    /// Change to the terrain height worked out by `update` for the `erode` pass.
+    pub erosion: Handle<Image>,
+}
+
+/// Velocity and soil moisture of a sleeping chunk, as two halves of a `u32` per texel.
+#[derive(Clone)]
+pub struct PackedChunk {
+    pub texture: Texture,
+    pub view: TextureView,
+}
+
+#[derive(Component, Clone)]
+pub struct FluidChunk {
+    pub coord: UVec2,
+    pub domain: FluidDomain,
+    pub state: ChunkState,
+    /// Water height, kept while asleep so the chunk can still be drawn.
+    pub height: Handle<Image>,
+    pub terrain_height: Handle<Image>,
+    /// Normals of the terrain, written by the `normals` pass.
+    pub terrain_normal: Handle<Image>,
+    /// Normals of the water surface on top of the terrain, written by the `normals` pass.
+    pub water_normal: Handle<Image>,
+    /// Foam on the water, kept while asleep like the height.
+    pub foam: Handle<Image>,
+    /// Light focused onto the terrain by the water surface, written by the `caustics` pass.
+    pub caustics: Handle<Image>,
+    /// How wet the terrain still is from water that stood on it, kept while asleep.
+    pub wetness: Handle<Image>,
+    /// False colours of a simulation texture, written by the `debug_view` pass while the debug
+    /// view is on.
+    pub debug: Handle<Image>,
+    pub working_set: Option<ChunkWorkingSet>,
+    pub packed: Option<PackedChunk>,
+    pub uniforms: Buffer,
+    /// Height, terrain height and soil moisture of the cells along the four edges of the chunk,
+    /// written every frame for the neighbours to read.
+    pub edges: Buffer,
+    /// Edge buffers of the awake neighbours to the west, east, south and north.
+    pub neighbour_edges: [Option<Buffer>; 4],
+    /// Number of tiles along x and z the chunk is drawn with.
+    pub tiles: UVec2,
+    /// Bounds of the tiles, written by the `tile_bounds` pass while the chunk is simulated.
+    pub tile_bounds: Buffer,
+    /// Where [`FluidChunk::tile_bounds`] is copied to be read back.
+    pub tile_bounds_mapped: Buffer,
+    /// Boundary conditions of the sides on the edge of the domain, walls everywhere else.
+    pub boundaries: [BoundaryCondition; 4],
+    pub plants: ChunkPlants,
+}
+
+impl FluidChunk {
+    /// Position of the chunk's first texel in the whole domain.
+    pub fn texel_offset(&self) -> IVec2 {
+        (self.coord * self.domain.resolution).as_ivec2()
+    }
+
+    /// Which neighbours exchange edges with this chunk, one bit per side.
+    pub fn neighbour_mask(&self) -> u32 {
+        self.neighbour_edges
+            .iter()
+            .enumerate()
+            .fold(0, |mask, (side, edges)| mask | (edges.is_some() as u32) << side)
+    }
+}
+
+impl ExtractComponent for FluidChunk {
+    type Query = &'static FluidChunk;
+    type Filter = ();
+    type Out = Self;
+
+    fn extract_component(item: QueryItem<'_, Self::Query>) -> Option<Self> {
+        Some(item.clone())
+    }
+}
+
+/// Starts the simulation over: every chunk generates its terrain and water again, the plants
+/// are cleared away.
+#[derive(Event, Clone, Debug, Default)]
+pub struct ResetSimulation {
+    /// Seed of the [`crate::terrain_generator::TerrainGenerator`] to start over with.
+    pub seed: Option<u32>,
+    /// Preset the terrain generator is switched to, keeping its seed unless `seed` is set.
+    pub preset: Option<TerrainPreset>,
+}
+
+impl ResetSimulation {
+    /// Starts over with the same terrain, or a freshly seeded one while shift is held.
+    pub const KEY: KeyCode = KeyCode::R;
+}
+
+fn send_reset_on_key(keyboard: Res<Input<KeyCode>>, mut resets: EventWriter<ResetSimulation>) {
+    if keyboard.just_pressed(ResetSimulation::KEY) {
+        let reseed = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        resets.send(ResetSimulation {
+            seed: reseed.then(rand::random),
+            preset: None,
+        });
+    }
+}
+
+/// Chunks whose pending passes the render world has submitted, with the state they were in.
+#[derive(Resource, Clone, Default)]
+pub struct FluidChunkFeedback(pub Arc<Mutex<Vec<(UVec2, ChunkState)>>>);
+
+fn fluid_texture(images: &mut Assets<Image>, resolution: UVec2) -> Handle<Image> {
+    let mut texture = Image::new_fill(
+        Extent3d {
+            width: resolution.x,
+            height: resolution.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::R32Float,
+    );
+    texture.texture_descriptor.usage = TextureUsages::COPY_DST
+        | TextureUsages::COPY_SRC
+        | TextureUsages::STORAGE_BINDING
+        | TextureUsages::TEXTURE_BINDING;
+    images.add(texture)
+}
+
+fn normal_texture(images: &mut Assets<Image>, resolution: UVec2) -> Handle<Image> {
+    let mut texture = Image::new_fill(
+        Extent3d {
+            width: resolution.x,
+            height: resolution.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        // pointing up, as half floats
+        &[0, 0, 0, 0x3c, 0, 0, 0, 0],
+        TextureFormat::Rgba16Float,
+    );
+    texture.texture_descriptor.usage =
+        TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
+    images.add(texture)
+}
+
+fn caustics_texture(images: &mut Assets<Image>, resolution: UVec2) -> Handle<Image> {
+    let mut texture = Image::new_fill(
+        Extent3d {
+            width: resolution.x,
+            height: resolution.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        // unfocused light, 1.0
+        &1.0f32.to_le_bytes(),
+        TextureFormat::R32Float,
+    );
+    texture.texture_descriptor.usage =
+        TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
+    images.add(texture)
+}
+
+fn debug_texture(images: &mut Assets<Image>, resolution: UVec2) -> Handle<Image> {
+    let mut texture = Image::new_fill(
+        Extent3d {
+            width: resolution.x,
+            height: resolution.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8Unorm,
+    );
+    texture.texture_descriptor.usage =
+        TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
+    images.add(texture)
+}
+
+fn working_set(images: &mut Assets<Image>, resolution: UVec2) -> ChunkWorkingSet {
+    ChunkWorkingSet {
+        height_out: fluid_texture(images, resolution),
+        velocity: fluid_texture(images, resolution),
+        soil_moisture: fluid_texture(images, resolution),
+        soil_moisture_out: fluid_texture(images, resolution),
+        suitability: fluid_texture(images, resolution),
+        foam_out: fluid_texture(images, resolution),
+        sediment: fluid_texture(images, resolution),
+        sediment_out: fluid_texture(images, resolution),
+        erosion: fluid_texture(images, resolution),
+    }
+}
+
+/// Spawns every chunk with the water and terrain tiles drawing it.
+pub fn spawn_fluid_chunks(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<WaterMaterial>>,
+    mut terrain_materials: ResMut<Assets<TerrainMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    render_device: Res<RenderDevice>,
+    domain: Res<FluidDomain>,
+    settings: Res<FluidChunkSettings>,
+    tile_settings: Res<FluidTileSettings>,
+    scene_color: Res<SceneColor>,
+    generator: Res<TerrainGenerator>,
+) {
+    for x in 0..settings.chunks.x {
+        for y in 0..settings.chunks.y {
+            let coord = UVec2::new(x, y);
+            let chunk_domain = domain.chunk(coord, settings.chunks);
+            let height = fluid_texture(&mut images, chunk_domain.resolution);
+            let terrain_height = fluid_texture(&mut images, chunk_domain.resolution);
+            let terrain_normal = normal_texture(&mut images, chunk_domain.resolution);
+            let water_normal = normal_texture(&mut images, chunk_domain.resolution);
+            let foam = fluid_texture(&mut images, chunk_domain.resolution);
+            let caustics = caustics_texture(&mut images, chunk_domain.resolution);
+            let wetness = fluid_texture(&mut images, chunk_domain.resolution);
+            let debug = debug_texture(&mut images, chunk_domain.resolution);
+            let edge_length = chunk_domain.resolution.max_element() as u64;
+
+            let water_material = materials.add(WaterMaterial {
+                height: Some(height.clone()),
+                terrain: Some(terrain_height.clone()),
+                normal: Some(water_normal.clone()),
+                foam: Some(foam.clone()),
+                scene_color: Some(scene_color.0.clone()),
+                alpha_mode: AlphaMode::Blend,
+                ..Default::default()
+            });
+            let terrain_material = terrain_materials.add(TerrainMaterial {
+                height: Some(terrain_height.clone()),
+                normal: Some(terrain_normal.clone()),
+                water: Some(height.clone()),
+                wetness: Some(wetness.clone()),
+                caustics: Some(caustics.clone()),
+                debug: Some(debug.clone()),
+                ..Default::default()
+            });
+
+            commands
+                .spawn((
+                    FluidChunk {
+                        coord,
+                        domain: chunk_domain,
+                        state: ChunkState::Generating,
+                        working_set: Some(working_set(&mut images, chunk_domain.resolution)),
+                        height,
+                        terrain_height,
+                        terrain_normal,
+                        water_normal,
+                        foam,
+                        caustics,
+                        wetness,
+                        debug,
+                        packed: None,
+                        uniforms: render_device.create_buffer(&BufferDescriptor {
+                            label: Some("fluid chunk uniforms"),
+                            size: std::mem::size_of::<FluidComputeUniforms>() as u64,
+                            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                            mapped_at_creation: false,
+                        }),
+                        edges: render_device.create_buffer(&BufferDescriptor {
+                            label: Some("fluid chunk edges"),
+                            size: 4 * edge_length * std::mem::size_of::<Vec4>() as u64,
+                            usage: BufferUsages::STORAGE,
+                            mapped_at_creation: false,
+                        }),
+                        neighbour_edges: Default::default(),
+                        tiles: tile_settings.tiles,
+                        tile_bounds: render_device.create_buffer(&BufferDescriptor {
+                            label: Some("fluid chunk tile bounds"),
+                            size: tile_bounds_size(tile_settings.tiles),
+                            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+                            mapped_at_creation: false,
+                        }),
+                        tile_bounds_mapped: render_device.create_buffer(&BufferDescriptor {
+                            label: Some("fluid chunk tile bounds mapped"),
+                            size: tile_bounds_size(tile_settings.tiles),
+                            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                            mapped_at_creation: false,
+                        }),
+                        boundaries: Default::default(),
+                        plants: ChunkPlants::new(&render_device, &chunk_domain),
+                    },
+                    ChunkWaterStats::default(),
+                    SpatialBundle::INHERITED_IDENTITY,
+                ))
+                .with_children(|chunk| {
+                    spawn_chunk_tiles(
+                        chunk,
+                        &mut meshes,
+                        &tile_settings,
+                        &chunk_domain,
+                        generator.height_range(),
+                        water_material,
+                        terrain_material,
+                    );
+                });
+        }
+    }
+}
+
+/// Sends every chunk back to [`ChunkState::Generating`] on [`ResetSimulation`], handing the
+/// sleeping ones working textures for the `init` pass to fill.
+pub fn reset_chunks(
+    mut resets: EventReader<ResetSimulation>,
+    mut images: ResMut<Assets<Image>>,
+    mut chunks: Query<&mut FluidChunk>,
+) {
+    if resets.is_empty() {
+        return;
+    }
+    resets.clear();
+    for mut chunk in &mut chunks {
+        if chunk.working_set.is_none() {
+            chunk.working_set = Some(working_set(&mut images, chunk.domain.resolution));
+        }
+        chunk.packed = None;
+        chunk.state = ChunkState::Generating;
+    }
+}
+
+/// Wakes up chunks near the player or a camera and puts far away ones to sleep.
+pub fn update_chunk_states(
+    settings: Res<FluidChunkSettings>,
+    feedback: Res<FluidChunkFeedback>,
+    mut images: ResMut<Assets<Image>>,
+    render_device: Res<RenderDevice>,
+    viewers: Query<&GlobalTransform, Or<(With<Player>, With<Camera>)>>,
+    mut chunks: Query<&mut FluidChunk>,
+) {
+    let submitted: Vec<_> = feedback.0.lock().unwrap().drain(..).collect();
+    let viewers: Vec<_> = viewers.iter().map(|t| t.translation().xz()).collect();
+
+    for mut chunk in &mut chunks {
+        // The render world keeps running a pass until we move on, so only the first report
+        // for the current state counts.
+        if submitted.contains(&(chunk.coord, chunk.state)) {
+            match chunk.state {
+                ChunkState::Generating | ChunkState::WakingUp => {
+                    chunk.packed = None;
+                    chunk.state = ChunkState::Awake;
+                }
+                ChunkState::FallingAsleep => {
+                    chunk.working_set = None;
+                    chunk.state = ChunkState::Asleep;
+                }
+                ChunkState::Awake | ChunkState::Asleep => {}
+            }
+        }
+
+        let near = viewers
+            .iter()
+            .any(|viewer| chunk.domain.distance(*viewer) < settings.wake_distance);
+        match (chunk.state, near) {
+            (ChunkState::Awake, false) => {
+                let texture = render_device.create_texture(&TextureDescriptor {
+                    label: Some("fluid chunk packed"),
+                    size: Extent3d {
+                        width: chunk.domain.resolution.x,
+                        height: chunk.domain.resolution.y,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: TextureFormat::R32Uint,
+                    usage: TextureUsages::STORAGE_BINDING,
+                    view_formats: &[],
+                });
+                let view = texture.create_view(&TextureViewDescriptor::default());
+                chunk.packed = Some(PackedChunk { texture, view });
+                chunk.state = ChunkState::FallingAsleep;
+            }
+            (ChunkState::Asleep, true) => {
+                chunk.working_set = Some(working_set(&mut images, chunk.domain.resolution));
+                chunk.state = ChunkState::WakingUp;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Hands every chunk the edge buffers of its awake neighbours and the boundary conditions of
+/// its sides on the edge of the domain. Sleeping neighbours act as walls.
+pub fn update_chunk_neighbours(
+    settings: Res<FluidChunkSettings>,
+    conditions: Res<FluidBoundaryConditions>,
+    mut chunks: Query<&mut FluidChunk>,
+) {
+    let awake: HashMap<_, _> = chunks
+        .iter()
+        .filter(|chunk| chunk.state == ChunkState::Awake)
+        .map(|chunk| (chunk.coord.as_ivec2(), chunk.edges.clone()))
+        .collect();
+    if conditions.is_changed() && conditions.has_unpaired_periodic() {
+        warn!("a periodic edge of the fluid domain is opposite one that isn't, both are periodic");
+    }
+    let grid = settings.chunks.as_ivec2();
+    let conditions = conditions.sides();
+    for mut chunk in &mut chunks {
+        let coord = chunk.coord.as_ivec2();
+        let sides = [IVec2::NEG_X, IVec2::X, IVec2::NEG_Y, IVec2::Y];
+        let mut neighbour_edges: [Option<Buffer>; 4] = Default::default();
+        let mut boundaries = [BoundaryCondition::Wall; 4];
+        for (side, offset) in sides.into_iter().enumerate() {
+            let mut neighbour = coord + offset;
+            if neighbour.cmplt(IVec2::ZERO).any() || neighbour.cmpge(grid).any() {
+                boundaries[side] = conditions[side];
+                if conditions[side] != BoundaryCondition::Periodic {
+                    continue;
+                }
+                // Wraps around to the chunk on the opposite edge, which is this one when there
+                // is a single chunk along the axis.
+                neighbour = neighbour.rem_euclid(grid);
+            }
+            neighbour_edges[side] = awake.get(&neighbour).cloned();
+        }
+        chunk.neighbour_edges = neighbour_edges;
+        chunk.boundaries = boundaries;
+    }
+}
+
+/// Follows the player into the chunk they are in.
+pub fn track_focus_chunk(
+    player: Query<&Transform, With<Player>>,
+    chunks: Query<&FluidChunk>,
+    mut genderfluid_image: ResMut<GenderfluidImage>,
+) {
+    let player = player.single().translation;
+    let Some(chunk) = chunks
+        .iter()
+        .find(|chunk| chunk.domain.contains_texel(chunk.domain.world_to_texel(player)))
+    else {
+        return;
+    };
+    if genderfluid_image.focus == chunk.coord {
+        return;
+    }
+
+    let gfi = &mut *genderfluid_image;
+    gfi.focus = chunk.coord;
+    gfi.focus_domain = chunk.domain;
+    gfi.terrain_height = chunk.terrain_height.clone();
+}
@@ -0,0 +1,288 @@
+//! Measurements of the simulation, recorded as Bevy [`Diagnostic`]s and logged periodically.
+//!
+//! The water is summed up by the `tile_bounds` pass, see [`ChunkWaterStats`]. The GPU time of
+//! the fluid and extract passes is measured with timestamp queries on adapters that support
+//! them.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics, LogDiagnosticsPlugin, RegisterDiagnostic},
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+use wgpu::{QuerySet, QuerySetDescriptor, QueryType, QUERY_RESOLVE_BUFFER_ALIGNMENT};
+
+use crate::{fluid_tiles::ChunkWaterStats, GpuReadback, Plant};
+
+pub const WATER_VOLUME: DiagnosticId =
+    DiagnosticId::from_u128(0x2d6a4f1e_8c3b_4e07_9a51_6f0e3c1d2b01);
+pub const WET_CELLS: DiagnosticId = DiagnosticId::from_u128(0x2d6a4f1e_8c3b_4e07_9a51_6f0e3c1d2b02);
+pub const MAX_VELOCITY: DiagnosticId =
+    DiagnosticId::from_u128(0x2d6a4f1e_8c3b_4e07_9a51_6f0e3c1d2b03);
+pub const PLANT_COUNT: DiagnosticId =
+    DiagnosticId::from_u128(0x2d6a4f1e_8c3b_4e07_9a51_6f0e3c1d2b04);
+pub const PLANT_HEALTH: DiagnosticId =
+    DiagnosticId::from_u128(0x2d6a4f1e_8c3b_4e07_9a51_6f0e3c1d2b05);
+pub const FLUID_PASS_TIME: DiagnosticId =
+    DiagnosticId::from_u128(0x2d6a4f1e_8c3b_4e07_9a51_6f0e3c1d2b06);
+pub const EXTRACT_PASS_TIME: DiagnosticId =
+    DiagnosticId::from_u128(0x2d6a4f1e_8c3b_4e07_9a51_6f0e3c1d2b07);
+
+pub struct FluidDiagnosticsPlugin {
+    /// How often the measurements are logged, `None` to only record them.
+    pub log_every: Option<Duration>,
+}
+
+impl Default for FluidDiagnosticsPlugin {
+    fn default() -> Self {
+        Self {
+            log_every: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+impl Plugin for FluidDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_diagnostic(Diagnostic::new(WATER_VOLUME, "fluid/water_volume", 20))
+            .register_diagnostic(Diagnostic::new(WET_CELLS, "fluid/wet_cells", 20))
+            .register_diagnostic(Diagnostic::new(MAX_VELOCITY, "fluid/max_velocity", 20))
+            .register_diagnostic(Diagnostic::new(PLANT_COUNT, "plants/count", 20))
+            .register_diagnostic(Diagnostic::new(PLANT_HEALTH, "plants/average_health", 20))
+            .register_diagnostic(
+                Diagnostic::new(FLUID_PASS_TIME, "gpu/fluid_pass", 20).with_suffix("ms"),
+            )
+            .register_diagnostic(
+                Diagnostic::new(EXTRACT_PASS_TIME, "gpu/extract_pass", 20).with_suffix("ms"),
+            )
+            .add_plugins(ExtractResourcePlugin::<PassTimestamps>::default())
+            .init_resource::<CopiedPasses>()
+            .add_systems(
+                PostUpdate,
+                (
+                    measure_water.after(GpuReadback::Read),
+                    measure_plants,
+                    copy_back_pass_times.in_set(GpuReadback::Copy),
+                    measure_pass_times.in_set(GpuReadback::Read),
+                ),
+            );
+        if let Some(wait_duration) = self.log_every {
+            app.add_plugins(LogDiagnosticsPlugin {
+                debug: false,
+                wait_duration,
+                filter: Some(vec![
+                    WATER_VOLUME,
+                    WET_CELLS,
+                    MAX_VELOCITY,
+                    PLANT_COUNT,
+                    PLANT_HEALTH,
+                    FLUID_PASS_TIME,
+                    EXTRACT_PASS_TIME,
+                ]),
+            });
+        }
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_device = app.world.resource::<RenderDevice>();
+        if render_device
+            .features()
+            .contains(WgpuFeatures::TIMESTAMP_QUERY)
+        {
+            let timestamps = PassTimestamps::new(render_device);
+            app.insert_resource(timestamps);
+        } else {
+            info!("the adapter has no timestamp queries, the GPU passes won't be timed");
+        }
+    }
+}
+
+/// Compute passes timed on the GPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimedPass {
+    Fluid,
+    Extract,
+}
+
+impl TimedPass {
+    const ALL: [TimedPass; 2] = [TimedPass::Fluid, TimedPass::Extract];
+
+    fn index(self) -> u32 {
+        self as u32
+    }
+
+    fn diagnostic(self) -> DiagnosticId {
+        match self {
+            TimedPass::Fluid => FLUID_PASS_TIME,
+            TimedPass::Extract => EXTRACT_PASS_TIME,
+        }
+    }
+
+    /// Where the timestamps of the pass are resolved to, resolving has to start aligned.
+    fn offset(self) -> u64 {
+        self.index() as u64 * QUERY_RESOLVE_BUFFER_ALIGNMENT
+    }
+}
+
+/// Timestamps written around the [`TimedPass`]es by their render graph nodes.
+#[derive(Resource, ExtractResource, Clone)]
+pub struct PassTimestamps {
+    query_set: Arc<QuerySet>,
+    /// Where the nodes resolve their timestamps to.
+    resolved: Buffer,
+    /// Where [`PassTimestamps::resolved`] is copied to be read back.
+    mapped: Buffer,
+    /// Passes resolved since the last copy back. The buffers keep the timestamps of passes the
+    /// nodes skip, these are only read back once.
+    resolved_passes: Arc<Mutex<Vec<TimedPass>>>,
+}
+
+impl PassTimestamps {
+    fn new(render_device: &RenderDevice) -> Self {
+        let size = TimedPass::ALL.len() as u64 * QUERY_RESOLVE_BUFFER_ALIGNMENT;
+        Self {
+            query_set: Arc::new(render_device.wgpu_device().create_query_set(
+                &QuerySetDescriptor {
+                    label: Some("fluid pass timestamps"),
+                    ty: QueryType::Timestamp,
+                    count: 2 * TimedPass::ALL.len() as u32,
+                },
+            )),
+            resolved: render_device.create_buffer(&BufferDescriptor {
+                label: Some("fluid pass timestamps resolved"),
+                size,
+                usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            mapped: render_device.create_buffer(&BufferDescriptor {
+                label: Some("fluid pass timestamps mapped"),
+                size,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            resolved_passes: default(),
+        }
+    }
+
+    /// Marks the start of `pass` in `encoder`.
+    pub fn start(&self, encoder: &mut CommandEncoder, pass: TimedPass) {
+        encoder.write_timestamp(&self.query_set, 2 * pass.index());
+    }
+
+    /// Marks the end of `pass` in `encoder` and resolves its timestamps.
+    pub fn end(&self, encoder: &mut CommandEncoder, pass: TimedPass) {
+        let first = 2 * pass.index();
+        encoder.write_timestamp(&self.query_set, first + 1);
+        encoder.resolve_query_set(
+            &self.query_set,
+            first..first + 2,
+            &self.resolved,
+            pass.offset(),
+        );
+        self.resolved_passes.lock().unwrap().push(pass);
+    }
+}
+
+fn measure_water(mut diagnostics: Diagnostics, chunks: Query<&ChunkWaterStats>) {
+    let (volume, wet_cells, max_speed) =
+        chunks
+            .iter()
+            .fold((0.0, 0, 0.0f32), |(volume, wet_cells, max_speed), stats| {
+                (
+                    volume + stats.volume as f64,
+                    wet_cells + stats.wet_cells,
+                    max_speed.max(stats.max_speed),
+                )
+            });
+    diagnostics.add_measurement(WATER_VOLUME, || volume);
+    diagnostics.add_measurement(WET_CELLS, || wet_cells as f64);
+    diagnostics.add_measurement(MAX_VELOCITY, || max_speed as f64);
+}
+
+fn measure_plants(mut diagnostics: Diagnostics, plants: Query<(&Plant, &Visibility)>) {
+    let (count, health) = plants
+        .iter()
+        .filter(|(_, visibility)| **visibility != Visibility::Hidden)
+        .fold((0, 0.0), |(count, health), (plant, _)| {
+            (count + 1, health + plant.health as f64)
+        });
+    diagnostics.add_measurement(PLANT_COUNT, || count as f64);
+    if count > 0 {
+        diagnostics.add_measurement(PLANT_HEALTH, || health / count as f64);
+    }
+}
+
+/// Passes whose timestamps are copied back this frame.
+#[derive(Resource, Default)]
+struct CopiedPasses(Vec<TimedPass>);
+
+/// Copies back the timestamps the nodes resolved since the last copy, they are mapped by the
+/// time [`measure_pass_times`] runs.
+fn copy_back_pass_times(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    timestamps: Option<Res<PassTimestamps>>,
+    mut copied: ResMut<CopiedPasses>,
+) {
+    let Some(timestamps) = timestamps else {
+        return;
+    };
+    copied.0 = timestamps
+        .resolved_passes
+        .lock()
+        .unwrap()
+        .drain(..)
+        .collect();
+    if copied.0.is_empty() {
+        return;
+    }
+    let mut command_encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("fluid pass timestamps copy back"),
+    });
+    command_encoder.copy_buffer_to_buffer(
+        &timestamps.resolved,
+        0,
+        &timestamps.mapped,
+        0,
+        timestamps.mapped.size(),
+    );
+    render_queue.submit([command_encoder.finish()]);
+    timestamps.mapped.slice(..).map_async(MapMode::Read, |_| {});
+}
+
+/// Records how long the passes took from the timestamps [`copy_back_pass_times`] read back.
+fn measure_pass_times(
+    render_queue: Res<RenderQueue>,
+    timestamps: Option<Res<PassTimestamps>>,
+    mut copied: ResMut<CopiedPasses>,
+    mut diagnostics: Diagnostics,
+) {
+    let Some(timestamps) = timestamps else {
+        return;
+    };
+    if copied.0.is_empty() {
+        return;
+    }
+    {
+        let range = timestamps.mapped.slice(..).get_mapped_range();
+        let nanoseconds_per_tick = render_queue.get_timestamp_period() as f64;
+        for pass in copied.0.drain(..) {
+            let offset = pass.offset() as usize;
+            let ticks: &[u64] = bytemuck::cast_slice(&range[offset..offset + 16]);
+            // the GPU timer wrapped around or was reset in between
+            if ticks[1] <= ticks[0] {
+                continue;
+            }
+            let milliseconds = (ticks[1] - ticks[0]) as f64 * nanoseconds_per_tick / 1e6;
+            diagnostics.add_measurement(pass.diagnostic(), || milliseconds);
+        }
+    }
+    timestamps.mapped.unmap();
+}
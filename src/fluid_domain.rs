@@ -0,0 +1,368 @@
+//! The mapping between world space and the fluid simulation grid.
+//!
+//! The simulation covers a rectangle of the xz plane. Positions on it can be expressed as
+//! world coordinates, as uv coordinates in `0..1` over the domain, as texels of the simulation
+//! textures or as the coarser cells of the plant grid. Everything that converts between these
+//! goes through [`FluidDomain`] so they can't drift apart.
+
+use bevy::{
+    math::Vec3Swizzles,
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use bytemuck::{Pod, Zeroable};
+
+use crate::{CELL_SIZE, SIZE};
+
+#[derive(Resource, Reflect, Clone, Copy, Debug, PartialEq)]
+#[reflect(Resource)]
+pub struct FluidDomain {
+    /// World position of the centre of the domain on the xz plane.
+    pub origin: Vec2,
+    /// World size of the domain along x and z.
+    pub extent: Vec2,
+    /// Number of texels of the simulation textures along x and z.
+    pub resolution: UVec2,
+    /// Number of texels along each side of a plant grid cell.
+    pub cell_size: u32,
+}
+
+impl Default for FluidDomain {
+    fn default() -> Self {
+        Self {
+            origin: Vec2::ZERO,
+            extent: Vec2::splat(5.0),
+            resolution: UVec2::splat(SIZE),
+            cell_size: CELL_SIZE,
+        }
+    }
+}
+
+/// The part of [`FluidDomain`] the shaders need to map world positions to uvs.
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct FluidDomainUniform {
+    /// World position of the corner at uv `(0, 0)`.
+    pub min: Vec2,
+    pub extent: Vec2,
+}
+
+impl FluidDomain {
+    /// World position of the corner at uv `(0, 0)`.
+    pub fn min(&self) -> Vec2 {
+        self.origin - self.extent / 2.0
+    }
+
+    /// World size of a single texel.
+    pub fn texel_size(&self) -> Vec2 {
+        self.extent / self.resolution.as_vec2()
+    }
+
+    /// Number of plant grid cells along x and z.
+    pub fn cell_count(&self) -> UVec2 {
+        self.resolution / self.cell_size
+    }
+
+    /// The part of the domain covered by chunk `coord`, when it is split into `chunks` equal
+    /// chunks along x and z.
+    pub fn chunk(&self, coord: UVec2, chunks: UVec2) -> FluidDomain {
+        let extent = self.extent / chunks.as_vec2();
+        FluidDomain {
+            origin: self.min() + (coord.as_vec2() + 0.5) * extent,
+            extent,
+            resolution: self.resolution / chunks,
+            cell_size: self.cell_size,
+        }
+    }
+
+    /// World distance from `point` on the xz plane to the domain, zero inside of it.
+    pub fn distance(&self, point: Vec2) -> f32 {
+        point.distance(point.clamp(self.min(), self.min() + self.extent))
+    }
+
+    pub fn world_to_uv(&self, world: Vec3) -> Vec2 {
+        (world.xz() - self.min()) / self.extent
+    }
+
+    /// The world position at `uv`, on the `y = 0` plane.
+    pub fn uv_to_world(&self, uv: Vec2) -> Vec3 {
+        let xz = self.min() + uv * self.extent;
+        Vec3::new(xz.x, 0.0, xz.y)
+    }
+
+    /// The texel containing `uv`. It may lie outside of the textures.
+    pub fn uv_to_texel(&self, uv: Vec2) -> IVec2 {
+        (uv * self.resolution.as_vec2()).floor().as_ivec2()
+    }
+
+    /// The uv of the centre of `texel`.
+    pub fn texel_to_uv(&self, texel: IVec2) -> Vec2 {
+        (texel.as_vec2() + 0.5) / self.resolution.as_vec2()
+    }
+
+    pub fn world_to_texel(&self, world: Vec3) -> IVec2 {
+        self.uv_to_texel(self.world_to_uv(world))
+    }
+
+    /// The world position of the centre of `texel`, on the `y = 0` plane.
+    pub fn texel_to_world(&self, texel: IVec2) -> Vec3 {
+        self.uv_to_world(self.texel_to_uv(texel))
+    }
+
+    pub fn contains_texel(&self, texel: IVec2) -> bool {
+        texel.cmpge(IVec2::ZERO).all() && texel.cmplt(self.resolution.as_ivec2()).all()
+    }
+
+    /// Row-major index of `texel` into a buffer covering the textures.
+    pub fn texel_index(&self, texel: IVec2) -> Option<usize> {
+        self.contains_texel(texel)
+            .then(|| (texel.y as u32 * self.resolution.x + texel.x as u32) as usize)
+    }
+
+    /// The plant grid cell containing `texel`, if it is inside the domain.
+    pub fn texel_to_cell(&self, texel: IVec2) -> Option<UVec2> {
+        let cell = texel.as_uvec2() / self.cell_size;
+        (self.contains_texel(texel) && cell.cmplt(self.cell_count()).all()).then_some(cell)
+    }
+
+    pub fn world_to_cell(&self, world: Vec3) -> Option<UVec2> {
+        self.texel_to_cell(self.world_to_texel(world))
+    }
+
+    /// The uv of a point inside `cell`, `offset` going from `0` to `1` across it.
+    pub fn cell_to_uv(&self, cell: UVec2, offset: Vec2) -> Vec2 {
+        (cell.as_vec2() + offset) * self.cell_size as f32 / self.resolution.as_vec2()
+    }
+
+    /// The world position of a point inside `cell`, on the `y = 0` plane.
+    pub fn cell_to_world(&self, cell: UVec2, offset: Vec2) -> Vec3 {
+        self.uv_to_world(self.cell_to_uv(cell, offset))
+    }
+
+    pub fn uniform(&self) -> FluidDomainUniform {
+        FluidDomainUniform {
+            min: self.min(),
+            extent: self.extent,
+        }
+    }
+
+    /// A grid over the texels `first..first + size`, with a vertex every `step` texels, in world
+    /// space and with uvs over the whole domain. A skirt `skirt` deep hangs off its border to
+    /// hide the cracks next to grids with a different `step`.
+    pub fn grid_mesh(&self, first: UVec2, size: UVec2, step: u32, skirt: f32) -> Mesh {
+        let count = size / step + 1;
+        let mut positions = vec![];
+        let mut uvs = vec![];
+        for y in 0..count.y {
+            for x in 0..count.x {
+                let uv = (first + UVec2::new(x, y) * step).as_vec2() / self.resolution.as_vec2();
+                positions.push(self.uv_to_world(uv).to_array());
+                uvs.push(uv.to_array());
+            }
+        }
+
+        let mut indices = vec![];
+        for y in 0..count.y - 1 {
+            for x in 0..count.x - 1 {
+                let i = y * count.x + x;
+                indices.extend([i, i + count.x, i + 1, i + 1, i + count.x, i + count.x + 1]);
+            }
+        }
+
+        // once around the border, back to the first vertex
+        let border: Vec<u32> = (0..count.x)
+            .chain((1..count.y).map(|y| y * count.x + count.x - 1))
+            .chain((0..count.x - 1).rev().map(|x| (count.y - 1) * count.x + x))
+            .chain((0..count.y - 1).rev().map(|y| y * count.x))
+            .collect();
+        for edge in border.windows(2) {
+            let bottom = positions.len() as u32;
+            for &top in edge {
+                let mut position = positions[top as usize];
+                position[1] -= skirt;
+                let uv = uvs[top as usize];
+                positions.push(position);
+                uvs.push(uv);
+            }
+            let (top, bottom) = ([edge[0], edge[1]], [bottom, bottom + 1]);
+            // both windings, so the skirt shows from either side
+            indices.extend([top[0], bottom[0], top[1], top[1], bottom[0], bottom[1]]);
+            indices.extend([top[0], top[1], bottom[0], top[1], bottom[1], bottom[0]]);
+        }
+
+        let normals = vec![[0.0, 1.0, 0.0]; positions.len()];
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::mesh::VertexAttributeValues;
+
+    use super::*;
+
+    fn domains() -> [FluidDomain; 3] {
+        [
+            FluidDomain::default(),
+            FluidDomain {
+                origin: Vec2::new(12.5, -3.0),
+                extent: Vec2::new(8.0, 2.0),
+                resolution: UVec2::new(512, 128),
+                cell_size: 16,
+            },
+            FluidDomain {
+                origin: Vec2::new(-100.0, 40.0),
+                extent: Vec2::splat(64.0),
+                resolution: UVec2::splat(64),
+                cell_size: 8,
+            },
+        ]
+    }
+
+    #[test]
+    fn world_uv_round_trip() {
+        for domain in domains() {
+            for uv in [Vec2::ZERO, Vec2::ONE, Vec2::new(0.25, 0.8), Vec2::new(-0.5, 1.5)] {
+                let world = domain.uv_to_world(uv);
+                assert_eq!(world.y, 0.0);
+                assert!(domain.world_to_uv(world).abs_diff_eq(uv, 1e-5));
+            }
+        }
+    }
+
+    #[test]
+    fn domain_corners() {
+        for domain in domains() {
+            let min = domain.uv_to_world(Vec2::ZERO);
+            let max = domain.uv_to_world(Vec2::ONE);
+            assert!(min.xz().abs_diff_eq(domain.origin - domain.extent / 2.0, 1e-5));
+            assert!(max.xz().abs_diff_eq(domain.origin + domain.extent / 2.0, 1e-5));
+            assert!(domain
+                .uv_to_world(Vec2::splat(0.5))
+                .xz()
+                .abs_diff_eq(domain.origin, 1e-5));
+        }
+    }
+
+    #[test]
+    fn texel_round_trip() {
+        for domain in domains() {
+            let last = domain.resolution.as_ivec2() - 1;
+            for texel in [IVec2::ZERO, last, IVec2::new(3, 7).min(last), IVec2::new(-1, -2)] {
+                assert_eq!(domain.world_to_texel(domain.texel_to_world(texel)), texel);
+                assert_eq!(domain.uv_to_texel(domain.texel_to_uv(texel)), texel);
+            }
+        }
+    }
+
+    #[test]
+    fn texels_cover_the_domain() {
+        for domain in domains() {
+            let last = domain.resolution.as_ivec2() - 1;
+            assert_eq!(domain.uv_to_texel(Vec2::ZERO), IVec2::ZERO);
+            assert_eq!(domain.uv_to_texel(Vec2::ONE - 1e-4), last);
+            assert!(domain.contains_texel(last));
+            assert!(!domain.contains_texel(last + IVec2::X));
+            assert!(!domain.contains_texel(IVec2::NEG_Y));
+            assert_eq!(domain.texel_index(IVec2::ZERO), Some(0));
+            assert_eq!(
+                domain.texel_index(last),
+                Some((domain.resolution.x * domain.resolution.y) as usize - 1)
+            );
+            assert_eq!(domain.texel_index(IVec2::NEG_ONE), None);
+        }
+    }
+
+    #[test]
+    fn cell_round_trip() {
+        for domain in domains() {
+            let count = domain.cell_count();
+            for cell in [UVec2::ZERO, count - 1, UVec2::new(1, 2).min(count - 1)] {
+                for offset in [Vec2::ZERO, Vec2::splat(0.5), Vec2::splat(0.999)] {
+                    let world = domain.cell_to_world(cell, offset);
+                    assert_eq!(domain.world_to_cell(world), Some(cell));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cells_outside_the_domain() {
+        for domain in domains() {
+            assert_eq!(domain.world_to_cell(domain.uv_to_world(Vec2::splat(-0.01))), None);
+            assert_eq!(domain.world_to_cell(domain.uv_to_world(Vec2::splat(1.01))), None);
+        }
+    }
+
+    #[test]
+    fn chunks_tile_the_domain() {
+        for domain in domains() {
+            let chunks = UVec2::new(4, 2);
+            for coord in [UVec2::ZERO, UVec2::new(1, 0), UVec2::new(3, 1)] {
+                let chunk = domain.chunk(coord, chunks);
+                let offset = (coord * chunk.resolution).as_ivec2();
+                for texel in [IVec2::ZERO, IVec2::new(2, 1), chunk.resolution.as_ivec2() - 1] {
+                    let world = chunk.texel_to_world(texel);
+                    assert_eq!(domain.world_to_texel(world), offset + texel);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn distance_to_the_domain() {
+        for domain in domains() {
+            assert_eq!(domain.distance(domain.origin), 0.0);
+            assert_eq!(domain.distance(domain.min()), 0.0);
+            let outside = domain.min() - Vec2::new(3.0, 0.0);
+            assert!((domain.distance(outside) - 3.0).abs() < 1e-5);
+        }
+    }
+
+    fn grid_positions(mesh: &Mesh) -> &[[f32; 3]] {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("grid has no positions");
+        };
+        positions
+    }
+
+    #[test]
+    fn grid_mesh_spans_the_texels() {
+        for domain in domains() {
+            let chunk = domain.chunk(UVec2::new(1, 0), UVec2::new(2, 2));
+            let first = chunk.resolution / 4;
+            let size = chunk.resolution / 2;
+            for step in [1, 2, 8] {
+                let mesh = chunk.grid_mesh(first, size, step, 0.0);
+                let points = grid_positions(&mesh).iter().map(|p| Vec2::new(p[0], p[2]));
+                let min = points.clone().fold(Vec2::INFINITY, Vec2::min);
+                let max = points.fold(Vec2::NEG_INFINITY, Vec2::max);
+                let texel = chunk.texel_size();
+                let corner = chunk.min() + first.as_vec2() * texel;
+                assert!(min.abs_diff_eq(corner, 1e-4));
+                assert!(max.abs_diff_eq(corner + size.as_vec2() * texel, 1e-4));
+            }
+        }
+    }
+
+    #[test]
+    fn grid_mesh_skirt() {
+        let domain = FluidDomain::default();
+        let size = UVec2::new(8, 4);
+        let mesh = domain.grid_mesh(UVec2::ZERO, size, 2, 0.25);
+        let positions = grid_positions(&mesh);
+        let grid = 5 * 3;
+        let border = 2 * (4 + 2);
+        assert_eq!(positions.len(), grid + 2 * border);
+        assert!(positions[..grid].iter().all(|p| p[1] == 0.0));
+        assert!(positions[grid..].iter().all(|p| p[1] == -0.25));
+        assert_eq!(mesh.indices().unwrap().len(), 6 * (4 * 2) + 12 * border);
+    }
+}
@@ -0,0 +1,95 @@
+//! How the water wears the terrain down and builds it back up, and how steep terrain slumps.
+
+use bevy::prelude::*;
+
+pub struct FluidErosionPlugin;
+
+impl Plugin for FluidErosionPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ErosionSettings>()
+            .init_resource::<ErosionSettings>()
+            .add_systems(Update, (start_thermal_erosion, count_down_thermal_erosion));
+    }
+}
+
+/// Rates of the hydraulic erosion the `update` pass runs along with the water, and of the
+/// thermal erosion of the `thermal` pass.
+///
+/// Sediment is measured as the terrain height it was taken from. Setting
+/// [`ErosionSettings::erosion_rate`] to zero leaves the terrain alone.
+#[derive(Resource, Reflect, Clone, Debug)]
+#[reflect(Resource)]
+pub struct ErosionSettings {
+    /// Sediment a cell of water can hold per unit of flow speed and terrain slope.
+    pub sediment_capacity: f32,
+    /// Fraction of the missing capacity picked up from the terrain per second.
+    pub erosion_rate: f32,
+    /// Fraction of the excess sediment dropped onto the terrain per second.
+    pub deposition_rate: f32,
+    /// Slope the capacity is worked out with on flatter terrain, so water still carries
+    /// sediment across plains and into lakes.
+    pub min_slope: f32,
+    /// Steepest slope, as the tangent of its angle, the terrain holds without slumping.
+    pub talus_slope: f32,
+    /// How fast terrain steeper than [`ErosionSettings::talus_slope`] slumps, per second.
+    pub thermal_rate: f32,
+    pub thermal: ThermalErosion,
+    /// How long thermal erosion runs when started with [`ErosionSettings::THERMAL_KEY`].
+    pub thermal_duration: f32,
+}
+
+impl ErosionSettings {
+    pub const THERMAL_KEY: KeyCode = KeyCode::T;
+
+    /// The thermal erosion rate the `thermal` pass runs with right now.
+    pub fn current_thermal_rate(&self) -> f32 {
+        match self.thermal {
+            ThermalErosion::Off => 0.0,
+            ThermalErosion::Continuous | ThermalErosion::For(_) => self.thermal_rate,
+        }
+    }
+}
+
+impl Default for ErosionSettings {
+    fn default() -> Self {
+        Self {
+            sediment_capacity: 0.01,
+            erosion_rate: 0.3,
+            deposition_rate: 0.3,
+            min_slope: 0.05,
+            talus_slope: 1.2,
+            thermal_rate: 2.0,
+            thermal: ThermalErosion::Off,
+            thermal_duration: 5.0,
+        }
+    }
+}
+
+/// When steep terrain slumps down onto its lower neighbours.
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ThermalErosion {
+    #[default]
+    Off,
+    Continuous,
+    /// Runs for the given seconds and turns itself off afterwards.
+    For(f32),
+}
+
+fn start_thermal_erosion(keyboard: Res<Input<KeyCode>>, mut settings: ResMut<ErosionSettings>) {
+    if keyboard.just_pressed(ErosionSettings::THERMAL_KEY)
+        && settings.thermal != ThermalErosion::Continuous
+    {
+        settings.thermal = ThermalErosion::For(settings.thermal_duration);
+    }
+}
+
+fn count_down_thermal_erosion(time: Res<Time>, mut settings: ResMut<ErosionSettings>) {
+    if let ThermalErosion::For(remaining) = settings.thermal {
+        let remaining = remaining - time.delta_seconds();
+        settings.thermal = if remaining > 0.0 {
+            ThermalErosion::For(remaining)
+        } else {
+            ThermalErosion::Off
+        };
+    }
+}
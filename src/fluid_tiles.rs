@@ -0,0 +1,275 @@
+//! The water and terrain meshes of the chunks.
+//!
+//! Every chunk is drawn as a grid of tiles. Each tile has a mesh per level of detail, picked by
+//! its distance to the closest camera, and bounds read back from the height textures so tiles
+//! off screen get culled.
+
+use bevy::{
+    math::Vec3Swizzles,
+    prelude::*,
+    render::{
+        primitives::Aabb,
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+        view::VisibilitySystems,
+    },
+};
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    fluid_chunks::FluidChunk, fluid_domain::FluidDomain, terrain_material::TerrainMaterial,
+    water_material::WaterMaterial, GpuReadback,
+};
+
+pub struct FluidTilePlugin;
+
+impl Plugin for FluidTilePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<FluidTileSettings>()
+            .init_resource::<FluidTileSettings>()
+            .init_resource::<PendingTileBounds>()
+            .add_systems(Update, select_tile_lods)
+            .add_systems(
+                PostUpdate,
+                (
+                    copy_back_tile_bounds.in_set(GpuReadback::Copy),
+                    update_tile_bounds
+                        .in_set(GpuReadback::Read)
+                        .before(VisibilitySystems::CheckVisibility),
+                ),
+            );
+    }
+}
+
+#[derive(Resource, Reflect, Clone, Debug)]
+#[reflect(Resource)]
+pub struct FluidTileSettings {
+    /// Number of tiles along x and z of every chunk, fixed once the chunks are spawned.
+    pub tiles: UVec2,
+    /// Tiles at least this far from every camera use the next coarser mesh. Every level halves
+    /// the vertices along each side.
+    pub lod_distances: Vec<f32>,
+    /// How far the skirts hanging off the tiles reach down.
+    pub skirt_depth: f32,
+}
+
+impl Default for FluidTileSettings {
+    fn default() -> Self {
+        Self {
+            tiles: UVec2::splat(4),
+            lod_distances: vec![1.5, 3.0, 6.0],
+            skirt_depth: 0.1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileSurface {
+    Water,
+    Terrain,
+}
+
+#[derive(Component)]
+pub struct FluidTile {
+    /// Position of the tile among the tiles of its chunk.
+    pub coord: UVec2,
+    pub domain: FluidDomain,
+    pub surface: TileSurface,
+    /// From the finest to the coarsest mesh.
+    pub lods: Vec<Handle<Mesh>>,
+    pub lod: usize,
+}
+
+/// Lowest and highest terrain and water surface of every tile of a chunk and the water on it,
+/// written by the `tile_bounds` pass.
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+#[repr(C)]
+struct TileBounds {
+    terrain_min: f32,
+    terrain_max: f32,
+    surface_min: f32,
+    surface_max: f32,
+    /// Water depth summed over the texels of the tile.
+    depth: f32,
+    wet_cells: f32,
+    max_speed: f32,
+    _padding: f32,
+}
+
+/// The water of a chunk as of the last time it was simulated, summed up from its tiles.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct ChunkWaterStats {
+    /// In world units cubed.
+    pub volume: f32,
+    /// Texels with more than a trace of water on them.
+    pub wet_cells: u32,
+    /// Fastest the water surface moves up or down anywhere on the chunk.
+    pub max_speed: f32,
+}
+
+/// Size of the buffer the `tile_bounds` pass writes for a chunk with `tiles` tiles.
+pub fn tile_bounds_size(tiles: UVec2) -> u64 {
+    (tiles.x * tiles.y) as u64 * std::mem::size_of::<TileBounds>() as u64
+}
+
+/// Spawns the water and terrain tiles covering `chunk`, with boxes spanning `heights` until
+/// the first bounds come back.
+pub fn spawn_chunk_tiles(
+    parent: &mut ChildBuilder,
+    meshes: &mut Assets<Mesh>,
+    settings: &FluidTileSettings,
+    chunk: &FluidDomain,
+    heights: Vec2,
+    water: Handle<WaterMaterial>,
+    terrain: Handle<TerrainMaterial>,
+) {
+    let size = chunk.resolution / settings.tiles;
+    // the coarsest level still has a quad per side
+    let levels = (settings.lod_distances.len() + 1).min(size.min_element().ilog2() as usize + 1);
+    for x in 0..settings.tiles.x {
+        for y in 0..settings.tiles.y {
+            let coord = UVec2::new(x, y);
+            let lods: Vec<_> = (0..levels)
+                .map(|lod| {
+                    meshes.add(chunk.grid_mesh(coord * size, size, 1 << lod, settings.skirt_depth))
+                })
+                .collect();
+            let domain = chunk.chunk(coord, settings.tiles);
+            let min = domain.min();
+            let aabb = Aabb::from_min_max(
+                Vec3::new(min.x, heights.x - settings.skirt_depth, min.y),
+                Vec3::new(min.x + domain.extent.x, heights.y, min.y + domain.extent.y),
+            );
+            let tile = |surface| FluidTile {
+                coord,
+                domain,
+                surface,
+                lods: lods.clone(),
+                lod: 0,
+            };
+            parent.spawn((
+                MaterialMeshBundle {
+                    mesh: lods[0].clone(),
+                    material: water.clone(),
+                    ..default()
+                },
+                aabb,
+                tile(TileSurface::Water),
+            ));
+            parent.spawn((
+                MaterialMeshBundle {
+                    mesh: lods[0].clone(),
+                    material: terrain.clone(),
+                    ..default()
+                },
+                aabb,
+                tile(TileSurface::Terrain),
+            ));
+        }
+    }
+}
+
+/// Gives every tile the mesh matching its distance to the closest camera.
+pub fn select_tile_lods(
+    settings: Res<FluidTileSettings>,
+    cameras: Query<&GlobalTransform, With<Camera>>,
+    mut tiles: Query<(&mut FluidTile, &mut Handle<Mesh>)>,
+) {
+    let cameras: Vec<_> = cameras.iter().map(|t| t.translation().xz()).collect();
+    for (mut tile, mut mesh) in &mut tiles {
+        let distance = cameras
+            .iter()
+            .map(|camera| tile.domain.distance(*camera))
+            .fold(f32::INFINITY, f32::min);
+        let lod = settings
+            .lod_distances
+            .iter()
+            .filter(|lod_distance| distance >= **lod_distance)
+            .count()
+            .min(tile.lods.len() - 1);
+        if tile.lod != lod {
+            tile.lod = lod;
+            *mesh = tile.lods[lod].clone();
+        }
+    }
+}
+
+/// Chunks whose tile bounds are copied back this frame.
+#[derive(Resource, Default)]
+struct PendingTileBounds(Vec<Entity>);
+
+/// Copies the bounds of the tiles of every simulated chunk back, they are mapped by the time
+/// [`update_tile_bounds`] runs.
+fn copy_back_tile_bounds(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    chunks: Query<(Entity, &FluidChunk)>,
+    mut pending: ResMut<PendingTileBounds>,
+) {
+    // sleeping chunks don't change
+    pending.0 = chunks
+        .iter()
+        .filter(|(_, chunk)| chunk.working_set.is_some())
+        .map(|(entity, _)| entity)
+        .collect();
+    if pending.0.is_empty() {
+        return;
+    }
+
+    let mut command_encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("tile bounds copy back"),
+    });
+    for (_, chunk) in chunks.iter_many(&pending.0) {
+        command_encoder.copy_buffer_to_buffer(
+            &chunk.tile_bounds,
+            0,
+            &chunk.tile_bounds_mapped,
+            0,
+            tile_bounds_size(chunk.tiles),
+        );
+    }
+    render_queue.submit([command_encoder.finish()]);
+    for (_, chunk) in chunks.iter_many(&pending.0) {
+        chunk
+            .tile_bounds_mapped
+            .slice(..)
+            .map_async(MapMode::Read, |_| {});
+    }
+}
+
+/// Fits the boxes of the tiles to the bounds [`copy_back_tile_bounds`] read back.
+pub fn update_tile_bounds(
+    settings: Res<FluidTileSettings>,
+    mut pending: ResMut<PendingTileBounds>,
+    mut chunks: Query<(&FluidChunk, &Children, &mut ChunkWaterStats)>,
+    mut tiles: Query<(&FluidTile, &mut Aabb)>,
+) {
+    let mut simulated = chunks.iter_many_mut(pending.0.drain(..));
+    while let Some((chunk, children, mut stats)) = simulated.fetch_next() {
+        {
+            let range = chunk.tile_bounds_mapped.slice(..).get_mapped_range();
+            let bounds: &[TileBounds] = bytemuck::cast_slice(&range[..]);
+            let texel_size = chunk.domain.texel_size();
+            *stats = ChunkWaterStats {
+                volume: bounds.iter().map(|b| b.depth).sum::<f32>() * texel_size.x * texel_size.y,
+                wet_cells: bounds.iter().map(|b| b.wet_cells as u32).sum(),
+                max_speed: bounds.iter().map(|b| b.max_speed).fold(0.0, f32::max),
+            };
+            let mut children = tiles.iter_many_mut(children.iter());
+            while let Some((tile, mut aabb)) = children.fetch_next() {
+                let bounds = bounds[(tile.coord.y * chunk.tiles.x + tile.coord.x) as usize];
+                let (low, high) = match tile.surface {
+                    TileSurface::Water => (bounds.surface_min, bounds.surface_max),
+                    TileSurface::Terrain => (bounds.terrain_min, bounds.terrain_max),
+                };
+                let min = tile.domain.min();
+                let max = min + tile.domain.extent;
+                *aabb = Aabb::from_min_max(
+                    Vec3::new(min.x, low - settings.skirt_depth, min.y),
+                    Vec3::new(max.x, high, max.y),
+                );
+            }
+        }
+        chunk.tile_bounds_mapped.unmap();
+    }
+}
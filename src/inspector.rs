@@ -0,0 +1,960 @@
+//! An in-game panel for tuning the simulation, the materials, the plants and the camera while
+//! everything runs.
+//!
+//! Every number, switch and enum reachable through [`Reflect`] on the inspected resources,
+//! materials and components is listed with buttons to change it. Changes are written straight
+//! into the inspected values, so they apply from the next frame on. Materials and components
+//! are read from the first one found and changed on all of them.
+
+use std::{any::TypeId, fmt};
+
+use bevy::{
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::*,
+    reflect::{
+        DynamicEnum, DynamicStruct, DynamicTuple, DynamicVariant, GetPath, ReflectRef, TypeInfo,
+        VariantInfo,
+    },
+    ui::{RelativeCursorPosition, UiSystem},
+    window::CursorGrabMode,
+};
+
+use crate::{
+    debug_view::DebugView, fluid_boundary::FluidBoundaryConditions,
+    fluid_chunks::FluidChunkSettings, fluid_domain::FluidDomain, fluid_erosion::ErosionSettings,
+    fluid_tiles::FluidTileSettings, orbit_camera::OrbitCameraController,
+    terrain_generator::TerrainGenerator, terrain_material::TerrainMaterial,
+    water_material::WaterMaterial, PlantSpecies,
+};
+
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Inspector::new(vec![
+            InspectedSection::resource::<ErosionSettings>("Erosion"),
+            InspectedSection::resource::<FluidBoundaryConditions>("Boundaries"),
+            InspectedSection::resource::<FluidChunkSettings>("Chunks").with_fixed(&["chunks"]),
+            // the tile meshes are built once when the chunks are spawned
+            InspectedSection::resource::<FluidTileSettings>("Tiles")
+                .with_fixed(&["tiles", "skirt_depth"]),
+            InspectedSection::resource::<FluidDomain>("Domain").with_fixed(&[
+                "origin",
+                "extent",
+                "resolution",
+                "cell_size",
+            ]),
+            // the preset is switched by resetting with it, see `ResetSimulation`
+            InspectedSection::resource::<TerrainGenerator>("Terrain generator (R to apply)")
+                .with_fixed(&["preset"]),
+            InspectedSection::resource::<PlantSpecies>("Plants"),
+            InspectedSection::material::<WaterMaterial>("Water material"),
+            // switched by the debug view
+            InspectedSection::material::<TerrainMaterial>("Terrain material")
+                .with_fixed(&["show_debug"]),
+            InspectedSection::component::<OrbitCameraController>("Camera"),
+            InspectedSection::resource::<DebugView>("Debug view"),
+        ]))
+        .add_systems(Startup, spawn_inspector)
+        .add_systems(PreUpdate, capture_pointer.after(UiSystem::Focus))
+        .add_systems(
+            Update,
+            (
+                toggle_inspector,
+                press_inspector_buttons,
+                apply_inspector_edits,
+                read_inspected_fields,
+                update_inspector_fields,
+                update_section_titles,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Run condition for systems that take the mouse over, like grabbing the cursor.
+pub fn inspector_closed(inspector: Res<Inspector>) -> bool {
+    !inspector.open
+}
+
+#[derive(Resource)]
+pub struct Inspector {
+    pub open: bool,
+    /// The section listing its fields, the others only show their title.
+    pub expanded: Option<usize>,
+    sections: Vec<InspectedSection>,
+    /// The fields of the expanded section as they were last read.
+    fields: Vec<InspectedField>,
+    /// Clicked buttons waiting to be applied.
+    edits: Vec<FieldEdit>,
+}
+
+impl Inspector {
+    pub const TOGGLE_KEY: KeyCode = KeyCode::F3;
+
+    fn new(sections: Vec<InspectedSection>) -> Self {
+        Self {
+            open: false,
+            expanded: None,
+            sections,
+            fields: vec![],
+            edits: vec![],
+        }
+    }
+}
+
+/// Something listed in the inspector, a resource, the materials of a kind or the components of
+/// a kind.
+#[derive(Clone, Copy)]
+struct InspectedSection {
+    title: &'static str,
+    /// Paths of fields that are shown but can't be changed, because changing them later has no
+    /// effect or breaks the simulation.
+    fixed: &'static [&'static str],
+    /// Hands the inspected value to the callback, if there is one.
+    read: fn(&mut World, &mut dyn FnMut(&dyn Reflect)),
+    /// Hands every inspected value to the callback.
+    edit: fn(&mut World, &mut dyn FnMut(&mut dyn Reflect)),
+}
+
+impl InspectedSection {
+    fn resource<R: Resource + Reflect>(title: &'static str) -> Self {
+        Self {
+            title,
+            fixed: &[],
+            read: read_resource::<R>,
+            edit: edit_resource::<R>,
+        }
+    }
+
+    fn material<M: Material + Reflect>(title: &'static str) -> Self {
+        Self {
+            title,
+            fixed: &[],
+            read: read_material::<M>,
+            edit: edit_material::<M>,
+        }
+    }
+
+    fn component<C: Component + Reflect>(title: &'static str) -> Self {
+        Self {
+            title,
+            fixed: &[],
+            read: read_component::<C>,
+            edit: edit_component::<C>,
+        }
+    }
+
+    fn with_fixed(self, fixed: &'static [&'static str]) -> Self {
+        Self { fixed, ..self }
+    }
+
+    fn is_fixed(&self, path: &str) -> bool {
+        self.fixed.iter().any(|fixed| {
+            path.strip_prefix(fixed)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+        })
+    }
+}
+
+fn read_resource<R: Resource + Reflect>(world: &mut World, read: &mut dyn FnMut(&dyn Reflect)) {
+    if let Some(resource) = world.get_resource::<R>() {
+        read(resource);
+    }
+}
+
+fn edit_resource<R: Resource + Reflect>(world: &mut World, edit: &mut dyn FnMut(&mut dyn Reflect)) {
+    if let Some(mut resource) = world.get_resource_mut::<R>() {
+        edit(&mut *resource);
+    }
+}
+
+fn read_material<M: Material + Reflect>(world: &mut World, read: &mut dyn FnMut(&dyn Reflect)) {
+    let materials = world.get_resource::<Assets<M>>();
+    if let Some((_, material)) = materials.and_then(|materials| materials.iter().next()) {
+        read(material);
+    }
+}
+
+fn edit_material<M: Material + Reflect>(world: &mut World, edit: &mut dyn FnMut(&mut dyn Reflect)) {
+    if let Some(mut materials) = world.get_resource_mut::<Assets<M>>() {
+        // marks the materials as modified, so they're prepared again
+        for (_, material) in materials.iter_mut() {
+            edit(material);
+        }
+    }
+}
+
+fn read_component<C: Component + Reflect>(world: &mut World, read: &mut dyn FnMut(&dyn Reflect)) {
+    let mut components = world.query::<&C>();
+    if let Some(component) = components.iter(world).next() {
+        read(component);
+    }
+}
+
+fn edit_component<C: Component + Reflect>(
+    world: &mut World,
+    edit: &mut dyn FnMut(&mut dyn Reflect),
+) {
+    let mut components = world.query::<&mut C>();
+    for mut component in components.iter_mut(world) {
+        edit(&mut *component);
+    }
+}
+
+/// A field of an inspected value, at a path [`GetPath`] understands.
+#[derive(Clone, Debug, PartialEq)]
+struct InspectedField {
+    path: String,
+    value: FieldValue,
+    editable: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum FieldValue {
+    Float(f64),
+    Integer(i64),
+    Bool(bool),
+    /// The variant an enum is set to.
+    Variant(String),
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Float(value) if *value != 0.0 && value.abs() < 0.001 => {
+                write!(f, "{value:.2e}")
+            }
+            FieldValue::Float(value) => write!(f, "{value:.3}"),
+            FieldValue::Integer(value) => write!(f, "{value}"),
+            FieldValue::Bool(value) => write!(f, "{value}"),
+            FieldValue::Variant(variant) => write!(f, "{variant}"),
+        }
+    }
+}
+
+/// Collects the numbers, switches and enums in `value`, recursing into structs, arrays, lists
+/// and the fields of enum variants. Anything else, like handles, is left out.
+fn collect_fields(path: &str, value: &dyn Reflect, fields: &mut Vec<InspectedField>) {
+    let mut push = |value| {
+        fields.push(InspectedField {
+            path: path.to_string(),
+            value,
+            editable: true,
+        })
+    };
+    let join = |name: &str| {
+        if path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{path}.{name}")
+        }
+    };
+    match value.reflect_ref() {
+        ReflectRef::Struct(value) => {
+            for (index, field) in value.iter_fields().enumerate() {
+                collect_fields(&join(value.name_at(index).unwrap()), field, fields);
+            }
+        }
+        ReflectRef::TupleStruct(value) => {
+            for (index, field) in value.iter_fields().enumerate() {
+                collect_fields(&join(&index.to_string()), field, fields);
+            }
+        }
+        ReflectRef::Array(value) => {
+            for (index, item) in value.iter().enumerate() {
+                collect_fields(&format!("{path}[{index}]"), item, fields);
+            }
+        }
+        ReflectRef::List(value) => {
+            for (index, item) in value.iter().enumerate() {
+                collect_fields(&format!("{path}[{index}]"), item, fields);
+            }
+        }
+        ReflectRef::Enum(variant) => {
+            if can_switch_variant(value) {
+                push(FieldValue::Variant(variant.variant_name().to_string()));
+            }
+            for (index, field) in variant.iter_fields().enumerate() {
+                let name = field
+                    .name()
+                    .map_or_else(|| index.to_string(), str::to_string);
+                collect_fields(&join(&name), field.value(), fields);
+            }
+        }
+        ReflectRef::Value(value) => {
+            if let Some(value) = value.downcast_ref::<f32>() {
+                push(FieldValue::Float(*value as f64));
+            } else if let Some(value) = value.downcast_ref::<f64>() {
+                push(FieldValue::Float(*value));
+            } else if let Some(value) = value.downcast_ref::<u32>() {
+                push(FieldValue::Integer(*value as i64));
+            } else if let Some(value) = value.downcast_ref::<i32>() {
+                push(FieldValue::Integer(*value as i64));
+            } else if let Some(value) = value.downcast_ref::<usize>() {
+                push(FieldValue::Integer(*value as i64));
+            } else if let Some(value) = value.downcast_ref::<bool>() {
+                push(FieldValue::Bool(*value));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether every variant of the enum `value` can be built from scratch, so the inspector can
+/// cycle through them. Colours keep their variant, switching colour spaces would zero them.
+fn can_switch_variant(value: &dyn Reflect) -> bool {
+    let Some(TypeInfo::Enum(info)) = value.get_represented_type_info() else {
+        return false;
+    };
+    !value.is::<Color>()
+        && info.variant_len() > 1
+        && info
+            .iter()
+            .all(|variant| default_variant(variant).is_some())
+}
+
+/// The variant with all its fields zeroed, if they're all numbers or switches.
+fn default_variant(variant: &VariantInfo) -> Option<DynamicVariant> {
+    Some(match variant {
+        VariantInfo::Unit(_) => DynamicVariant::Unit,
+        VariantInfo::Tuple(info) => {
+            let mut tuple = DynamicTuple::default();
+            for field in info.iter() {
+                tuple.insert_boxed(zero(field.type_id())?);
+            }
+            DynamicVariant::Tuple(tuple)
+        }
+        VariantInfo::Struct(info) => {
+            let mut fields = DynamicStruct::default();
+            for field in info.iter() {
+                fields.insert_boxed(field.name(), zero(field.type_id())?);
+            }
+            DynamicVariant::Struct(fields)
+        }
+    })
+}
+
+fn zero(type_id: TypeId) -> Option<Box<dyn Reflect>> {
+    if type_id == TypeId::of::<f32>() {
+        Some(Box::new(0.0f32))
+    } else if type_id == TypeId::of::<f64>() {
+        Some(Box::new(0.0f64))
+    } else if type_id == TypeId::of::<u32>() {
+        Some(Box::new(0u32))
+    } else if type_id == TypeId::of::<i32>() {
+        Some(Box::new(0i32))
+    } else if type_id == TypeId::of::<usize>() {
+        Some(Box::new(0usize))
+    } else if type_id == TypeId::of::<bool>() {
+        Some(Box::new(false))
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EditAction {
+    Decrease,
+    Increase,
+    Toggle,
+    NextVariant,
+}
+
+#[derive(Clone, Debug)]
+struct FieldEdit {
+    section: usize,
+    path: String,
+    action: EditAction,
+    /// Shift was held, numbers take bigger steps.
+    coarse: bool,
+}
+
+/// Smallest step of a float, so fields can be moved away from zero.
+const MIN_STEP: f64 = 0.001;
+
+/// Floats step by a tenth of their size, or their whole size with `coarse`, so small rates and
+/// large heights can both be tuned with the same buttons. Integers step by one, or ten.
+fn edit_field(field: &mut dyn Reflect, action: EditAction, coarse: bool) {
+    let direction = match action {
+        EditAction::Decrease => -1.0,
+        EditAction::Increase => 1.0,
+        EditAction::Toggle => {
+            if let Some(value) = field.downcast_mut::<bool>() {
+                *value = !*value;
+            }
+            return;
+        }
+        EditAction::NextVariant => {
+            next_variant(field);
+            return;
+        }
+    };
+    let step_float = |value: f64| {
+        let fraction = if coarse { 1.0 } else { 0.1 };
+        let stepped = value + direction * (value.abs() * fraction).max(MIN_STEP);
+        // don't leave rounding errors behind when crossing zero
+        if stepped.abs() < MIN_STEP / 2.0 {
+            0.0
+        } else {
+            stepped
+        }
+    };
+    let step = direction as i64 * if coarse { 10 } else { 1 };
+    if let Some(value) = field.downcast_mut::<f32>() {
+        *value = step_float(*value as f64) as f32;
+    } else if let Some(value) = field.downcast_mut::<f64>() {
+        *value = step_float(*value);
+    } else if let Some(value) = field.downcast_mut::<u32>() {
+        *value = value.saturating_add_signed(step as i32);
+    } else if let Some(value) = field.downcast_mut::<i32>() {
+        *value = value.saturating_add(step as i32);
+    } else if let Some(value) = field.downcast_mut::<usize>() {
+        *value = value.saturating_add_signed(step as isize);
+    }
+}
+
+fn next_variant(field: &mut dyn Reflect) {
+    let (ReflectRef::Enum(value), Some(TypeInfo::Enum(info))) =
+        (field.reflect_ref(), field.get_represented_type_info())
+    else {
+        return;
+    };
+    let next = info
+        .variant_at((value.variant_index() + 1) % info.variant_len())
+        .unwrap();
+    if let Some(variant) = default_variant(next) {
+        field.apply(&DynamicEnum::new(next.name(), variant));
+    }
+}
+
+#[derive(Component)]
+struct InspectorPanel;
+
+/// The part of the panel below its title, clips the sections when they don't fit.
+#[derive(Component)]
+struct InspectorViewport;
+
+/// The sections, moved up and down to scroll through them.
+#[derive(Component)]
+struct InspectorContent;
+
+#[derive(Component)]
+struct SectionTitle(usize);
+
+/// Holds the rows of the fields of a section while it's expanded.
+#[derive(Component)]
+struct SectionBody(usize);
+
+/// The value of the field at this index of [`Inspector::fields`].
+#[derive(Component)]
+struct FieldValueText(usize);
+
+#[derive(Component, Clone, Debug)]
+enum InspectorButton {
+    /// Expands the section, or collapses it if it already is.
+    Section(usize),
+    Edit(FieldEdit),
+}
+
+const FONT_SIZE: f32 = 14.0;
+const BUTTON_COLOR: Color = Color::rgb(0.2, 0.2, 0.25);
+const HOVERED_BUTTON_COLOR: Color = Color::rgb(0.3, 0.3, 0.38);
+const PRESSED_BUTTON_COLOR: Color = Color::rgb(0.4, 0.4, 0.5);
+/// How far a line of mouse wheel scrolling moves the panel.
+const LINE_HEIGHT: f32 = 20.0;
+
+fn text_style() -> TextStyle {
+    TextStyle {
+        font_size: FONT_SIZE,
+        color: Color::WHITE,
+        ..default()
+    }
+}
+
+fn spawn_inspector(mut commands: Commands, inspector: Res<Inspector>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(8.0),
+                    right: Val::Px(8.0),
+                    width: Val::Px(400.0),
+                    max_height: Val::Percent(90.0),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(6.0),
+                    padding: UiRect::all(Val::Px(6.0)),
+                    ..default()
+                },
+                background_color: Color::rgba(0.05, 0.05, 0.08, 0.85).into(),
+                ..default()
+            },
+            RelativeCursorPosition::default(),
+            InspectorPanel,
+        ))
+        .with_children(|panel| {
+            panel.spawn(TextBundle::from_section(
+                format!(
+                    "Inspector ({:?} to close, shift for bigger steps)",
+                    Inspector::TOGGLE_KEY
+                ),
+                text_style(),
+            ));
+            panel
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Column,
+                            min_height: Val::Px(0.0),
+                            overflow: Overflow::clip_y(),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    InspectorViewport,
+                ))
+                .with_children(|viewport| {
+                    viewport
+                        .spawn((
+                            NodeBundle {
+                                style: Style {
+                                    flex_direction: FlexDirection::Column,
+                                    row_gap: Val::Px(2.0),
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            InspectorContent,
+                        ))
+                        .with_children(|content| {
+                            for (index, section) in inspector.sections.iter().enumerate() {
+                                content
+                                    .spawn((
+                                        ButtonBundle {
+                                            style: Style {
+                                                padding: UiRect::all(Val::Px(3.0)),
+                                                ..default()
+                                            },
+                                            background_color: BUTTON_COLOR.into(),
+                                            ..default()
+                                        },
+                                        InspectorButton::Section(index),
+                                    ))
+                                    .with_children(|button| {
+                                        button.spawn((
+                                            TextBundle::from_section(section.title, text_style()),
+                                            SectionTitle(index),
+                                        ));
+                                    });
+                                content.spawn((
+                                    NodeBundle {
+                                        style: Style {
+                                            flex_direction: FlexDirection::Column,
+                                            row_gap: Val::Px(1.0),
+                                            padding: UiRect::left(Val::Px(8.0)),
+                                            ..default()
+                                        },
+                                        ..default()
+                                    },
+                                    SectionBody(index),
+                                ));
+                            }
+                        });
+                });
+        });
+}
+
+/// Opens and closes the inspector, freeing the cursor to click on it.
+fn toggle_inspector(
+    keyboard: Res<Input<KeyCode>>,
+    mut inspector: ResMut<Inspector>,
+    mut panels: Query<&mut Style, With<InspectorPanel>>,
+    mut windows: Query<&mut Window>,
+) {
+    if !keyboard.just_pressed(Inspector::TOGGLE_KEY) {
+        return;
+    }
+    inspector.open = !inspector.open;
+    for mut style in &mut panels {
+        style.display = if inspector.open {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+    if inspector.open {
+        for mut window in &mut windows {
+            window.cursor.grab_mode = CursorGrabMode::None;
+            window.cursor.visible = true;
+        }
+    }
+}
+
+/// Keeps the mouse to the inspector while it's open. The camera stops following the mouse, and
+/// clicks and scrolling over the panel don't reach the simulation.
+fn capture_pointer(
+    inspector: Res<Inspector>,
+    mut mouse_buttons: ResMut<Input<MouseButton>>,
+    mut mouse_motion: ResMut<Events<MouseMotion>>,
+    mut mouse_wheel: ResMut<Events<MouseWheel>>,
+    panels: Query<&RelativeCursorPosition, With<InspectorPanel>>,
+    viewports: Query<&Node, With<InspectorViewport>>,
+    mut contents: Query<(&mut Style, &Node), With<InspectorContent>>,
+) {
+    if !inspector.open {
+        return;
+    }
+    mouse_motion.clear();
+    if !panels.iter().any(|cursor| cursor.mouse_over()) {
+        return;
+    }
+    // the buttons already saw the click
+    mouse_buttons.reset_all();
+    let scroll: f32 = mouse_wheel
+        .drain()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y * LINE_HEIGHT,
+            MouseScrollUnit::Pixel => event.y,
+        })
+        .sum();
+
+    let Ok(viewport) = viewports.get_single() else {
+        return;
+    };
+    for (mut style, content) in &mut contents {
+        let top = match style.top {
+            Val::Px(top) => top,
+            _ => 0.0,
+        };
+        let lowest = (viewport.size().y - content.size().y).min(0.0);
+        style.top = Val::Px((top + scroll).clamp(lowest, 0.0));
+    }
+}
+
+fn press_inspector_buttons(
+    keyboard: Res<Input<KeyCode>>,
+    mut inspector: ResMut<Inspector>,
+    mut buttons: Query<
+        (&Interaction, &InspectorButton, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+) {
+    for (interaction, button, mut color) in &mut buttons {
+        *color = match interaction {
+            Interaction::Pressed => PRESSED_BUTTON_COLOR,
+            Interaction::Hovered => HOVERED_BUTTON_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        }
+        .into();
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            InspectorButton::Section(section) => {
+                inspector.expanded = if inspector.expanded == Some(*section) {
+                    None
+                } else {
+                    Some(*section)
+                };
+            }
+            InspectorButton::Edit(edit) => inspector.edits.push(FieldEdit {
+                coarse: keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+                ..edit.clone()
+            }),
+        }
+    }
+}
+
+fn apply_inspector_edits(world: &mut World) {
+    if world.resource::<Inspector>().edits.is_empty() {
+        return;
+    }
+    let edits = std::mem::take(&mut world.resource_mut::<Inspector>().edits);
+    for edit in edits {
+        let section = world.resource::<Inspector>().sections[edit.section];
+        (section.edit)(
+            world,
+            &mut |value| match value.reflect_path_mut(&edit.path) {
+                Ok(field) => edit_field(field, edit.action, edit.coarse),
+                Err(err) => warn!("can't change `{}` of {}: {}", edit.path, section.title, err),
+            },
+        );
+    }
+}
+
+fn read_inspected_fields(world: &mut World) {
+    let inspector = world.resource::<Inspector>();
+    let section = match inspector.expanded {
+        Some(section) if inspector.open => inspector.sections[section],
+        _ => return,
+    };
+    let mut fields = vec![];
+    (section.read)(world, &mut |value| collect_fields("", value, &mut fields));
+    for field in &mut fields {
+        field.editable &= !section.is_fixed(&field.path);
+    }
+    let mut inspector = world.resource_mut::<Inspector>();
+    // leaves the resource unchanged while the values stay the same
+    if inspector.fields != fields {
+        inspector.fields = fields;
+    }
+}
+
+/// Lists the fields of the expanded section, spawning their rows again whenever a different
+/// section is expanded or an enum switches to a variant with other fields.
+fn update_inspector_fields(
+    mut commands: Commands,
+    inspector: Res<Inspector>,
+    mut shown: Local<(Option<usize>, Vec<(String, bool)>)>,
+    bodies: Query<(Entity, &SectionBody)>,
+    mut values: Query<(&mut Text, &FieldValueText)>,
+) {
+    if !inspector.is_changed() {
+        return;
+    }
+    let rows: Vec<_> = inspector
+        .fields
+        .iter()
+        .map(|field| (field.path.clone(), field.editable))
+        .collect();
+    if shown.0 == inspector.expanded && shown.1 == rows {
+        for (mut text, FieldValueText(index)) in &mut values {
+            if let Some(field) = inspector.fields.get(*index) {
+                text.sections[0].value = field.value.to_string();
+            }
+        }
+        return;
+    }
+
+    for (body, SectionBody(section)) in &bodies {
+        commands.entity(body).despawn_descendants();
+        if inspector.expanded != Some(*section) {
+            continue;
+        }
+        commands.entity(body).with_children(|body| {
+            for (index, field) in inspector.fields.iter().enumerate() {
+                spawn_field_row(body, *section, index, field);
+            }
+        });
+    }
+    *shown = (inspector.expanded, rows);
+}
+
+fn spawn_field_row(body: &mut ChildBuilder, section: usize, index: usize, field: &InspectedField) {
+    let button = |action| {
+        (
+            ButtonBundle {
+                style: Style {
+                    padding: UiRect::horizontal(Val::Px(4.0)),
+                    ..default()
+                },
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            },
+            InspectorButton::Edit(FieldEdit {
+                section,
+                path: field.path.clone(),
+                action,
+                coarse: false,
+            }),
+        )
+    };
+    let value = (
+        TextBundle::from_section(field.value.to_string(), text_style()),
+        FieldValueText(index),
+    );
+
+    body.spawn(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Row,
+            justify_content: JustifyContent::SpaceBetween,
+            column_gap: Val::Px(8.0),
+            ..default()
+        },
+        ..default()
+    })
+    .with_children(|row| {
+        row.spawn(TextBundle::from_section(
+            field.path.clone(),
+            TextStyle {
+                color: Color::rgb(0.75, 0.75, 0.8),
+                ..text_style()
+            },
+        ));
+        if !field.editable {
+            row.spawn(value);
+            return;
+        }
+        match field.value {
+            FieldValue::Float(_) | FieldValue::Integer(_) => {
+                row.spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(4.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|controls| {
+                    controls
+                        .spawn(button(EditAction::Decrease))
+                        .with_children(|button| {
+                            button.spawn(TextBundle::from_section("-", text_style()));
+                        });
+                    controls.spawn(value);
+                    controls
+                        .spawn(button(EditAction::Increase))
+                        .with_children(|button| {
+                            button.spawn(TextBundle::from_section("+", text_style()));
+                        });
+                });
+            }
+            FieldValue::Bool(_) => {
+                row.spawn(button(EditAction::Toggle))
+                    .with_children(|button| {
+                        button.spawn(value);
+                    });
+            }
+            FieldValue::Variant(_) => {
+                row.spawn(button(EditAction::NextVariant))
+                    .with_children(|button| {
+                        button.spawn(value);
+                    });
+            }
+        }
+    });
+}
+
+fn update_section_titles(inspector: Res<Inspector>, mut titles: Query<(&mut Text, &SectionTitle)>) {
+    if !inspector.is_changed() {
+        return;
+    }
+    for (mut text, SectionTitle(section)) in &mut titles {
+        let marker = if inspector.expanded == Some(*section) {
+            "-"
+        } else {
+            "+"
+        };
+        text.sections[0].value = format!("{marker} {}", inspector.sections[*section].title);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fluid_boundary::BoundaryCondition;
+
+    #[derive(Reflect, Default)]
+    struct Sample {
+        rate: f32,
+        steps: u32,
+        enabled: bool,
+        extent: Vec2,
+        ranges: [f64; 2],
+        name: String,
+    }
+
+    fn field(path: &str, value: FieldValue) -> InspectedField {
+        InspectedField {
+            path: path.to_string(),
+            value,
+            editable: true,
+        }
+    }
+
+    #[test]
+    fn numbers_and_switches_are_collected() {
+        let sample = Sample {
+            rate: 0.5,
+            steps: 3,
+            enabled: true,
+            extent: Vec2::new(1.0, 2.0),
+            ranges: [4.0, 5.0],
+            name: "left out".to_string(),
+        };
+        let mut fields = vec![];
+        collect_fields("", &sample, &mut fields);
+        assert_eq!(
+            fields,
+            [
+                field("rate", FieldValue::Float(0.5)),
+                field("steps", FieldValue::Integer(3)),
+                field("enabled", FieldValue::Bool(true)),
+                field("extent.x", FieldValue::Float(1.0)),
+                field("extent.y", FieldValue::Float(2.0)),
+                field("ranges[0]", FieldValue::Float(4.0)),
+                field("ranges[1]", FieldValue::Float(5.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn variants_and_their_fields_are_collected() {
+        let conditions = FluidBoundaryConditions {
+            west: BoundaryCondition::Inflow { level: 1.5 },
+            ..default()
+        };
+        let mut fields = vec![];
+        collect_fields("", &conditions, &mut fields);
+        assert_eq!(
+            fields,
+            [
+                field("west", FieldValue::Variant("Inflow".to_string())),
+                field("west.level", FieldValue::Float(1.5)),
+                field("east", FieldValue::Variant("Wall".to_string())),
+                field("south", FieldValue::Variant("Wall".to_string())),
+                field("north", FieldValue::Variant("Wall".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn floats_step_by_their_size() {
+        let mut value = 1.0f32;
+        edit_field(&mut value, EditAction::Increase, false);
+        assert!((value - 1.1).abs() < 1e-6);
+        edit_field(&mut value, EditAction::Decrease, true);
+        assert_eq!(value, 0.0);
+
+        edit_field(&mut value, EditAction::Increase, false);
+        assert!((value as f64 - MIN_STEP).abs() < 1e-9);
+        edit_field(&mut value, EditAction::Decrease, false);
+        assert_eq!(value, 0.0);
+    }
+
+    #[test]
+    fn integers_step_by_one_or_ten() {
+        let mut value = 0u32;
+        edit_field(&mut value, EditAction::Decrease, false);
+        assert_eq!(value, 0);
+        edit_field(&mut value, EditAction::Increase, true);
+        assert_eq!(value, 10);
+        edit_field(&mut value, EditAction::Decrease, false);
+        assert_eq!(value, 9);
+    }
+
+    #[test]
+    fn switches_toggle_and_variants_cycle() {
+        let mut enabled = false;
+        edit_field(&mut enabled, EditAction::Toggle, false);
+        assert!(enabled);
+
+        let mut condition = BoundaryCondition::Periodic;
+        edit_field(&mut condition, EditAction::NextVariant, false);
+        assert_eq!(condition, BoundaryCondition::Inflow { level: 0.0 });
+        edit_field(&mut condition, EditAction::NextVariant, false);
+        assert_eq!(condition, BoundaryCondition::Wall);
+    }
+
+    #[test]
+    fn fixed_paths_cover_their_fields() {
+        let section =
+            InspectedSection::resource::<FluidTileSettings>("Tiles").with_fixed(&["tiles"]);
+        assert!(section.is_fixed("tiles"));
+        assert!(section.is_fixed("tiles.x"));
+        assert!(section.is_fixed("tiles[0]"));
+        assert!(!section.is_fixed("tiles_per_chunk"));
+        assert!(!section.is_fixed("skirt_depth"));
+    }
+}
@@ -1,1004 +1,1753 @@
-//! A compute shader that simulates Genderfluid.
-//!
-//! Compute shaders use the GPU for computing arbitrary information, that may be independent of what
-//! is rendered to the screen.
-
-mod extract_heights;
-mod orbit_camera;
-mod water_pbr_material;
-use bevy::{
-    core::{Pod, Zeroable},
-    gltf::Gltf,
-    prelude::*,
-    reflect::TypeUuid,
-    render::{
-        extract_resource::{ExtractResource, ExtractResourcePlugin},
-        render_asset::RenderAssets,
-        render_graph::{self, RenderGraph},
-        render_resource::*,
-        renderer::{RenderContext, RenderDevice, RenderQueue},
-        view::NoFrustumCulling,
-        Render, RenderApp, RenderSet,
-    },
-    window::WindowPlugin,
-};
-use bevy_shader_utils::ShaderUtilsPlugin;
-use extract_heights::{
-    GenderfluidExtractNode, GenderfluidExtractPipeline, GenderfluidImage, QueryPosition,
-};
-use orbit_camera::{ControlEvent, OrbitCameraBundle, OrbitCameraController, OrbitCameraPlugin};
-use rand::Rng;
-use smooth_bevy_cameras::LookTransformPlugin;
-use std::{
-    borrow::Cow, char::from_u32_unchecked, f32::consts::PI, mem::size_of, ops::Deref, sync::Arc,
-};
-use water_pbr_material::WaterStandardMaterial;
-use wgpu::Maintain;
-
-const SIZE: u32 = 256;
-const WORKGROUP_SIZE: u32 = 8;
-const CELL_SIZE: u32 = 32;
-const EXTRACT_BUFFER_SIZE: u32 = (SIZE / CELL_SIZE).pow(2) + 1;
-
-// Define a struct to keep some information about our entity.
-// Here it's an arbitrary movement speed, the spawn location, and a maximum distance from it.
-#[derive(Component)]
-struct Movable {
-    spawn: Vec3,
-    max_distance: f32,
-    speed: f32,
-}
-
-// Implement a utility function for easier Movable struct creation.
-impl Movable {
-    fn new(spawn: Vec3) -> Self {
-        Movable {
-            spawn,
-            max_distance: 5.0,
-            speed: 2.0,
-        }
-    }
-}
-
-#[derive(Component)]
-pub struct Plant {
-    pub health: f32,
-    pub is_no_longer_baby: bool,
-	pub was_se_fuer_ne_zelle_is: (usize, usize),
-}
-
-#[derive(Resource)]
-pub struct PlantGrid {
-    pub grid: Vec<Vec<Option<Entity>>>,
-}
-
-#[derive(Resource)]
-struct PlantAsset(Handle<Scene>);
-
-impl Default for PlantGrid {
-    fn default() -> Self {
-        Self {
-            grid: vec![
-                vec![None; SIZE as usize / CELL_SIZE as usize];
-                SIZE as usize / CELL_SIZE as usize
-            ],
-        }
-    }
-}
-
-#[derive(Default, Component)]
-pub struct SphereController {
-    pub enabled: bool,
-    pub translate_sensitivity: f32,
-}
-
-#[derive(Event)]
-pub enum SphereControlEvent {
-    Translate(Vec3),
-}
-
-// const ATTRIBUTE_BLEND_COLOR: MeshVertexAttribute =
-//     MeshVertexAttribute::new("BlendColor", 988540917, VertexFormat::Float32x4);
-
-// A simple 3D scene with light shining over a cube sitting on a plane.
-fn main() {
-    App::new()
-        .add_plugins((
-            DefaultPlugins.set(WindowPlugin {
-                primary_window: Some(Window {
-                    // uncomment for unthrottled FPS
-                    // present_mode: bevy::window::PresentMode::AutoNoVsync,
-                    ..default()
-                }),
-                ..default()
-            }),
-            GenderfluidComputePlugin,
-            OrbitCameraPlugin::default(),
-            LookTransformPlugin,
-            ShaderUtilsPlugin,
-            MaterialPlugin::<WaterStandardMaterial>::default(),
-        ))
-        .add_event::<SphereControlEvent>()
-        .insert_resource(PlantGrid::default())
-        .add_systems(Startup, setup)
-        .add_systems(
-            Update,
-            (
-                sphere_input_map,
-                move_sphere,
-                cursor_grab_system,
-                update_camera_target,
-                update_plant_health,
-            ),
-        )
-        .add_systems(
-            PostUpdate,
-            (unmap_fluid_buffers, prepare_fluid_compute_uniforms).chain(),
-        )
-        .run();
-}
-
-use bevy::window::CursorGrabMode;
-
-fn cursor_grab_system(
-    mut windows: Query<&mut Window>,
-    btn: Res<Input<MouseButton>>,
-    key: Res<Input<KeyCode>>,
-) {
-    let mut window = windows.single_mut();
-
-    if btn.just_pressed(MouseButton::Left) {
-        window.cursor.grab_mode = CursorGrabMode::Locked;
-        window.cursor.visible = false;
-    }
-
-    if key.just_pressed(KeyCode::Escape) {
-        window.cursor.grab_mode = CursorGrabMode::None;
-        window.cursor.visible = true;
-    }
-}
-
-fn update_camera_target(
-    mut events: EventWriter<ControlEvent>,
-    player: Query<&Transform, With<Player>>,
-) {
-    events.send(ControlEvent::NewTarget(player.single().translation));
-}
-
-#[derive(Resource, Reflect, Debug, Clone, TypeUuid, ShaderType, Pod, Zeroable, Copy)]
-#[repr(C)]
-#[uuid = "61e3fe7d-e307-4d7f-a060-35fff2cba963"]
-struct FluidComputeUniforms {
-    player_position: Vec2,
-    click: u32,
-    _padding: u32,
-}
-
-// fn spawn_plants(
-//     mut commands: Commands,
-//     time: Res<Time>,
-//     mut plant_grid: ResMut<PlantGrid>,
-//     plant_asset: Res<PlantAsset>,
-// ) {
-//     // Some condition to control when to spawn a new plant, e.g., every second
-//     static mut LAST_SPAWN_TIME: f64 = 0.0;
-//     let current_time = time.elapsed_seconds_f64();
-//     if current_time - unsafe { LAST_SPAWN_TIME } < 0.015 {
-//         return;
-//     }
-//     unsafe {
-//         LAST_SPAWN_TIME = current_time;
-//     }
-
-//     let mut rng = rand::thread_rng();
-//     use rand::Rng;
-
-//     // Loop through the grid to find an empty spot to place a new plant
-//     for i in 0..(SIZE / CELL_SIZE) {
-//         for j in 0..(SIZE / CELL_SIZE) {
-//             if plant_grid.grid[i as usize][j as usize].is_none() {
-//                 let offset_x: f32 = rng.gen_range(0.0..=CELL_SIZE as f32);
-//                 let offset_z: f32 = rng.gen_range(0.0..=CELL_SIZE as f32);
-
-//                 let world_x = i as f32 * CELL_SIZE as f32 + offset_x - SIZE as f32 / 2.0;
-//                 let world_z = j as f32 * CELL_SIZE as f32 + offset_z - SIZE as f32 / 2.0;
-
-//                 // Spawn a new plant entity
-//                 let new_plant = commands
-//                     .spawn(SceneBundle {
-//                         scene: plant_asset.0.clone(),
-//                         transform: Transform::from_xyz(world_x * 0.02, 2.0, world_z * 0.02)
-//                             .with_scale(Vec3::splat(0.02)),
-//                         ..Default::default()
-//                     })
-//                     .insert(Plant::default())
-//                     .id();
-
-//                 // Update the grid
-//                 plant_grid.grid[i as usize][j as usize] = Some(new_plant);
-
-//                 // Possibly break if you only want to spawn one plant per update
-//                 return;
-//             }
-//         }
-//     }
-// }
-
-fn update_plant_health(
-    time: Res<Time>,
-    mut plant_grid: ResMut<PlantGrid>,
-    mut plants: Query<(&mut Transform, &mut Plant, &mut Visibility)>,
-    mut commands: Commands,
-    plant_asset: Res<PlantAsset>,
-    spheres: Query<&Transform, (With<SphereController>, Without<Plant>)>,
-) {
-    // let ball_transform = spheres.single();
-
-    // let current_time = time.elapsed_seconds() as f32;
-    // let dt = time.delta_seconds() as f32;
-
-
-            // grow_plant_at(
-                // i,
-                // j,
-                // dt,
-                // ball_transform,
-                // &mut plant_grid,
-                // &mut plants,
-                // &mut commands,
-                // &plant_asset,
-            // )
-        // }
-    // }
-}
-
-fn health_curve(x: f32, difficulty: f32) -> f32 {
-    let health_peak_marker = 3.0;
-    1.0 / (1.0 + (x - health_peak_marker).powf(2.0)).powf(difficulty) - 0.1
-}
-
-fn should_spawn_based_on_distance(
-    existing_object_transform: &Transform,
-    spawn_x: f32,
-    spawn_z: f32,
-    max_radius: f32,
-) -> bool {
-    let existing_pos = existing_object_transform.translation;
-    let distance_vec = Vec2::new(spawn_x - existing_pos.x, spawn_z - existing_pos.z);
-    let distance = distance_vec.length();
-
-    // println!("distance: {}", distance);
-
-    if distance > max_radius {
-        return false;
-    }
-
-    let mut rng = rand::thread_rng();
-    let random_val: f32 = rng.gen(); // Generates a float between 0.0 and 1.0
-
-    // Make it more likely to spawn the closer it is to the existing object
-    let probability = 1.0 - (distance / max_radius);
-
-    random_val < probability
-}
-
-fn grow_plant_at(
-    i: u32,
-    j: u32,
-    dt: f32,
-    ball_transform: &Transform,
-    plant_grid: &mut ResMut<PlantGrid>,
-	plant: (&mut Transform, &mut Plant, &mut Visibility),
-    commands: &mut Commands,
-    plant_asset: &PlantAsset,
-	terrain_height: f32,
-	water_height: f32,
-) {
-    if let Some(plant_entity) = plant_grid.grid[i as usize][j as usize] {
-        let (mut transform, mut actual_plant, mut visibility) = plant;
-            actual_plant.health = health_curve(water_height * 5.0, 1.25);
-			transform.scale = Vec3::splat((actual_plant.health + 0.001337).min(0.05).max(0.0));
-            println!("Health: {}", actual_plant.health);
-            if actual_plant.is_no_longer_baby && actual_plant.health <= 0.0 {
-                *visibility = Visibility::Hidden;
-            } else if !actual_plant.is_no_longer_baby && actual_plant.health > 0.0 {
-                actual_plant.is_no_longer_baby = true;
-            }
-        
-    } else if plant_grid.grid[i as usize][j as usize].is_none() {
-        // let mut rng = rand::thread_rng();
-
-        // let offset_x: f32 = rng.gen_range(0.0..=CELL_SIZE as f32);
-        // let offset_z: f32 = rng.gen_range(0.0..=CELL_SIZE as f32);
-
-        // let world_x = (i as f32 * CELL_SIZE as f32 + offset_x - SIZE as f32 / 2.0) * 0.02;
-        // let world_z = (j as f32 * CELL_SIZE as f32 + offset_z - SIZE as f32 / 2.0) * 0.02;
-
-        // if should_spawn_based_on_distance(&ball_transform, world_x, world_z, 1.0337) {
-            // println!("Spawn the object!");
-
-            // Spawn a new plant entity
-            // let new_plant = commands
-                // .spawn(SceneBundle {
-                    // scene: plant_asset.0.clone(),
-                    // transform: Transform::from_xyz(world_x, terrain_height, world_z)
-                        // .with_scale(Vec3::splat(0.02)),
-                    // ..Default::default()
-                // })
-                // .insert(Plant::default())
-                // .id();
-
-            // Update the grid
-            // plant_grid.grid[i as usize][j as usize] = Some(new_plant);
-        // }
-    }
-}
-
-/// set up a simple 3D scene
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut standard_materials: ResMut<Assets<StandardMaterial>>,
-    mut custom_materials: ResMut<Assets<WaterStandardMaterial>>,
-    mut images: ResMut<Assets<Image>>,
-    asset_server: Res<AssetServer>,
-    render_device: Res<RenderDevice>,
-) {
-    let eye = Vec3::new(-2.0, 5.0, 5.1);
-    let target = Vec3::default();
-    let controllllller = OrbitCameraController::default();
-
-    let plant = asset_server.load("glowingflower2.glb#Scene0");
-    commands.insert_resource(PlantAsset(plant));
-    // to position our 3d model, simply use the Transform
-    // in the SceneBundle
-    // commands
-    //     .spawn(SceneBundle {
-    //         scene: plant,
-    //         transform: Transform::from_xyz(0.0, 2.0, -5.0).with_scale(Vec3::splat(0.1)),
-    //         ..Default::default()
-    //     })
-    //     .insert(Plant::default());
-
-    commands
-        .spawn(Camera3dBundle::default())
-        .insert(OrbitCameraBundle::new(controllllller, eye, target, Vec3::Y));
-
-    // light
-    commands.spawn(PointLightBundle {
-        point_light: PointLight {
-            intensity: 1500.0,
-            shadows_enabled: true,
-            ..default()
-        },
-        transform: Transform::from_xyz(4.0, 8.0, 4.0),
-        ..default()
-    });
-
-    // Add a cube to visualize translation.
-    let entity_spawn = Vec3::new(0.0, 3.0, 0.0);
-    commands
-        .spawn((
-            PbrBundle {
-                mesh: meshes.add(Mesh::from(shape::UVSphere {
-                    radius: 0.1337,
-                    sectors: 32,
-                    stacks: 32,
-                })),
-                material: standard_materials.add(Color::WHITE.into()),
-                transform: Transform::from_translation(entity_spawn),
-                ..default()
-            },
-            Movable::new(entity_spawn),
-        ))
-        .insert(SphereController {
-            enabled: true,
-            translate_sensitivity: 2.0,
-            ..Default::default()
-        })
-        .insert(Player);
-
-    let mut make_texture = || {
-        let mut texture = Image::new_fill(
-            Extent3d {
-                width: SIZE,
-                height: SIZE,
-                depth_or_array_layers: 1,
-            },
-            TextureDimension::D2,
-            &[0, 0, 0, 255],
-            TextureFormat::R32Float,
-        );
-        texture.texture_descriptor.usage = TextureUsages::COPY_DST
-            | TextureUsages::STORAGE_BINDING
-            | TextureUsages::TEXTURE_BINDING;
-        let image = images.add(texture);
-        image
-    };
-    let height1 = make_texture();
-    let height2 = make_texture();
-    let velocity = make_texture();
-    let terrain_height = make_texture();
-
-    let material_handle = custom_materials.add(WaterStandardMaterial {
-        height: Some(height1.clone()),
-        velocity: Some(velocity.clone()),
-        terrain: Some(terrain_height.clone()),
-        base_color: Color::hsla(200.0, 1.0, 0.5, 0.8),
-        alpha_mode: AlphaMode::Blend,
-        // metallic: 0.5,
-        reflectance: 1.0,
-        is_water: 1,
-        ..Default::default()
-    });
-
-    commands
-        .spawn(MaterialMeshBundle {
-            mesh: meshes.add(
-                shape::Plane {
-                    size: 5.0,
-                    subdivisions: SIZE,
-                }
-                .into(),
-            ),
-            material: material_handle,
-            ..default()
-        })
-        .insert(NoFrustumCulling);
-
-    let terrain_material_handle = custom_materials.add(WaterStandardMaterial {
-        height: Some(terrain_height.clone()),
-        base_color: Color::hsla(22.0, 0.6, 0.28, 1.0),
-        alpha_mode: AlphaMode::Opaque,
-        // metallic: 0.5,
-        reflectance: 0.2,
-        is_water: 0,
-        ..Default::default()
-    });
-
-    commands
-        .spawn(MaterialMeshBundle {
-            mesh: meshes.add(
-                shape::Plane {
-                    size: 5.0,
-                    subdivisions: SIZE,
-                }
-                .into(),
-            ),
-            material: terrain_material_handle,
-            ..default()
-        })
-        .insert(NoFrustumCulling);
-
-    let water_compute_uniforms_buffer = render_device.create_buffer(&BufferDescriptor {
-        label: Some("fluid compute uniforms"),
-        size: std::mem::size_of::<FluidComputeUniforms>() as u64,
-        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
-
-    let extract_positions = render_device.create_buffer(&BufferDescriptor {
-        label: Some("fluid extract positions"),
-        size: std::mem::size_of::<QueryPosition>() as u64 * EXTRACT_BUFFER_SIZE as u64,
-        usage: BufferUsages::STORAGE
-            | BufferUsages::COPY_DST
-            | BufferUsages::COPY_SRC,
-        mapped_at_creation: false,
-    });
-
-    let extract_height = render_device.create_buffer(&BufferDescriptor {
-        label: Some("fluid extract height"),
-        size: std::mem::size_of::<f32>() as u64 * EXTRACT_BUFFER_SIZE as u64,
-        usage: BufferUsages::STORAGE
-            | BufferUsages::COPY_DST
-            | BufferUsages::COPY_SRC,
-        mapped_at_creation: false,
-    });
-
-    let extract_terrain_height = render_device.create_buffer(&BufferDescriptor {
-        label: Some("fluid extract terrain height"),
-        size: std::mem::size_of::<f32>() as u64 * EXTRACT_BUFFER_SIZE as u64,
-        usage: BufferUsages::STORAGE
-            | BufferUsages::COPY_DST
-            | BufferUsages::COPY_SRC,
-        mapped_at_creation: false,
-    });
-
-    let extract_height_mapped = render_device.create_buffer(&BufferDescriptor {
-        label: Some("fluid extract height"),
-        size: std::mem::size_of::<f32>() as u64 * EXTRACT_BUFFER_SIZE as u64,
-        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
-        mapped_at_creation: true,
-    });
-
-    let extract_terrain_height_mapped = render_device.create_buffer(&BufferDescriptor {
-        label: Some("fluid extract terrain height"),
-        size: std::mem::size_of::<f32>() as u64 * EXTRACT_BUFFER_SIZE as u64,
-        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
-        mapped_at_creation: true,
-    });
-
-    commands.insert_resource(GenderfluidImage {
-        height1,
-        height2,
-        velocity,
-        terrain_height,
-        uniforms: water_compute_uniforms_buffer,
-        extract_positions,
-        extract_height,
-        extract_terrain_height,
-        extract_height_mapped,
-        extract_terrain_height_mapped,
-    });
-}
-
-fn unmap_fluid_buffers(gfi: Res<GenderfluidImage>) {
-    gfi.extract_height_mapped.unmap();
-    gfi.extract_terrain_height_mapped.unmap();
-}
-
-// This system will move all Movable entities with a Transform
-pub fn move_sphere(
-    mut spheres: Query<(&mut Transform, &mut SphereController)>,
-    mut events: EventReader<SphereControlEvent>,
-    timer: Res<Time>,
-) {
-    for (mut transform, controller) in &mut spheres {
-        if !controller.enabled {
-            continue;
-        }
-
-        for event in events.iter() {
-            match event {
-                SphereControlEvent::Translate(dir) => {
-                    let movement = *dir * timer.delta_seconds();
-                    // println!("moving: {}", movement);
-                    transform.translation += movement;
-                }
-            }
-        }
-    }
-}
-
-pub struct GenderfluidComputePlugin;
-
-impl Plugin for GenderfluidComputePlugin {
-    fn build(&self, app: &mut App) {
-        // Extract the genderfluid image resource from the main world into the render world
-        // for operation on by the compute shader and display on the sprite.
-        app.add_plugins(ExtractResourcePlugin::<GenderfluidImage>::default());
-        let render_app = app.sub_app_mut(RenderApp);
-        render_app.add_systems(Render, queue_bind_group.in_set(RenderSet::Queue));
-        render_app.add_systems(
-            Render,
-            extract_heights::queue_extract_bind_group.in_set(RenderSet::Queue),
-        );
-
-        let mut render_graph = render_app.world.resource_mut::<RenderGraph>();
-        render_graph.add_node("genderfluid", GenderfluidNode::default());
-        render_graph.add_node("genderfluid extract", GenderfluidExtractNode::default());
-        render_graph.add_node_edge("genderfluid", bevy::render::main_graph::node::CAMERA_DRIVER);
-        render_graph.add_node_edge(
-            "genderfluid extract",
-            bevy::render::main_graph::node::CAMERA_DRIVER,
-        );
-    }
-
-    fn finish(&self, app: &mut App) {
-        let render_app = app.sub_app_mut(RenderApp);
-        render_app.init_resource::<GenderfluidPipeline>();
-        render_app.init_resource::<GenderfluidExtractPipeline>();
-    }
-}
-
-#[derive(Component)]
-pub struct Player;
-
-fn prepare_fluid_compute_uniforms(
-    btn: Res<Input<MouseButton>>,
-    render_queue: Res<RenderQueue>,
-    render_device: Res<RenderDevice>,
-    genderfluidimage: ResMut<GenderfluidImage>,
-    mut player: Query<&mut Transform, (With<Player>, Without<Plant>)>,
-	time: Res<Time>,
-    mut plant_grid: ResMut<PlantGrid>,
-    mut plants: Query<(&mut Transform, &mut Plant, &mut Visibility), Without<Player>>,
-    mut commands: Commands,
-    plant_asset: Res<PlantAsset>,
-) {
-    let current_time = time.elapsed_seconds() as f32;
-    let dt = time.delta_seconds() as f32;
-
-    // write `time.seconds_since_startup` as a `&[u8]`
-    // into the time buffer at offset 0.
-    render_queue.write_buffer(
-        &genderfluidimage.uniforms,
-        0,
-        bevy::core::bytes_of(&FluidComputeUniforms {
-            player_position: {
-                let t = player.single().translation;
-                Vec2::new(t.x, t.z) / 5.0 + 0.5
-            },
-            click: btn.pressed(MouseButton::Left) as u32,
-            _padding: 0,
-        }),
-    );
-    let mut query_positions = vec![];
-    let map_translation = |translation: Vec3| QueryPosition {
-        x: ((translation.x / 5.0 + 0.5) * SIZE as f32) as i32,
-        y: ((translation.z / 5.0 + 0.5) * SIZE as f32) as i32,
-    };
-    query_positions.push(map_translation(player.single().translation));
-	
-    for plant in plants.into_iter() {
-        query_positions.push(map_translation(plant.0.translation));
-    }
-	let mut rng = rand::thread_rng();
-    let mut new_plant_query_offset = query_positions.len();
-	let mut spawn_positions = vec![];
-	let mut spawn_grid_positions = vec![];
-    for i in 0..(SIZE / CELL_SIZE) {
-        for j in 0..(SIZE / CELL_SIZE) {
-			
-        let offset_x: f32 = rng.gen_range(0.0..=CELL_SIZE as f32);
-        let offset_z: f32 = rng.gen_range(0.0..=CELL_SIZE as f32);
-			let world_x = (i as f32 * CELL_SIZE as f32 + offset_x - SIZE as f32 / 2.0) * 0.02;
-			let world_z = (j as f32 * CELL_SIZE as f32 + offset_z - SIZE as f32 / 2.0) * 0.02;
-			
-			let existing_pos = player.single().translation;
-			let distance_vec = Vec2::new(world_x - existing_pos.x, world_z - existing_pos.z);
-			let distance = distance_vec.length();
-			if distance < 0.2 && plant_grid.grid[i as usize][j as usize].is_none() {
-				let spawn_pos = Vec3::new(world_x, 0.0, world_z);
-				spawn_positions.push(spawn_pos);
-				spawn_grid_positions.push((i, j));
-				query_positions.push(map_translation(spawn_pos));
-			}
-		}
-	}
-	
-	render_queue.write_buffer(
-        &genderfluidimage.extract_positions,
-        0,
-        bevy::core::cast_slice(&query_positions),
-    );
-
-    let mut command_encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
-        label: Some("extract heights copy back"),
-    });
-    command_encoder.copy_buffer_to_buffer(
-        &genderfluidimage.extract_height,
-        0,
-        &genderfluidimage.extract_height_mapped,
-        0,
-        ((SIZE / CELL_SIZE).pow(2) as usize * size_of::<f32>()) as u64,
-    );
-    command_encoder.copy_buffer_to_buffer(
-        &genderfluidimage.extract_terrain_height,
-        0,
-        &genderfluidimage.extract_terrain_height_mapped,
-        0,
-        ((SIZE / CELL_SIZE).pow(2) as usize * size_of::<f32>()) as u64,
-    );
-    render_queue.submit(vec![command_encoder.finish()].into_iter());
-
-    let height_slice = genderfluidimage.extract_height_mapped.slice(..);
-    height_slice.map_async(MapMode::Read, |_| {});
-    let terrain_height_slice = genderfluidimage.extract_terrain_height_mapped.slice(..);
-    terrain_height_slice.map_async(MapMode::Read, |_| {});
-
-    // Poll the device in a blocking manner so that our future resolves.
-    // In an actual application, `device.poll(...)` should
-    // be called in an event loop or on another thread.
-    render_device.poll(Maintain::Wait);
-
-    let height: Vec<_> = height_slice
-        .get_mapped_range()
-        .chunks_exact(4)
-        .map(|h| f32::from_ne_bytes(h.try_into().unwrap()))
-        .collect();
-
-    let terrain_height: Vec<_> = terrain_height_slice
-        .get_mapped_range()
-        .chunks_exact(4)
-        .map(|h| f32::from_ne_bytes(h.try_into().unwrap()))
-        .collect();
-    let y_trans = player.single().translation.y;
-	let new_y_trans = height[0] + terrain_height[0];
-	if new_y_trans.is_finite() && new_y_trans.abs() < 10.0 {
-		println!("now changing to {}", y_trans);
-		player.single_mut().translation.y = new_y_trans + 0.1337;
-	}
-	dbg!(height[0]);
-	
-    for (i, mut plant) in plants.iter_mut().enumerate() {
-        plant.0.translation.y = terrain_height[i+1];
-		grow_plant_at(
-			plant.1.was_se_fuer_ne_zelle_is.0 as u32,
-			plant.1.was_se_fuer_ne_zelle_is.1 as u32,
-			dt,
-			player.single(),
-			&mut plant_grid,
-			(&mut *plant.0, &mut *plant.1, &mut plant.2),
-			&mut commands,
-			&plant_asset,
-			terrain_height[i+1],
-			height[i+1],
-		);
-    }
-	
-	for i in new_plant_query_offset..query_positions.len() {
-		if height[0] > 0.02 {
-			dbg!(height[i]);
-		let (actual_i, actual_j) = spawn_grid_positions[i - new_plant_query_offset];
-		let world_pos = spawn_positions[i - new_plant_query_offset];
-		// Spawn a new plant entity
-		let new_plant = commands
-			.spawn(SceneBundle {
-				scene: plant_asset.0.clone(),
-				transform: Transform::from_xyz(world_pos.x, terrain_height[i], world_pos.z)
-					.with_scale(Vec3::splat(0.0)),
-				..Default::default()
-			})
-			.insert(Plant {
-            health: -0.001337,
-            is_no_longer_baby: false,
-				was_se_fuer_ne_zelle_is: (actual_i as usize, actual_j as usize)
-        })
-			.id();
-
-		// Update the grid
-		plant_grid.grid[actual_i as usize][actual_j as usize] = Some(new_plant);
-		}
-	}
-}
-
-#[derive(Resource)]
-struct GenderfluidImageBindGroup(BindGroup);
-
-fn queue_bind_group(
-    mut commands: Commands,
-    pipeline: Res<GenderfluidPipeline>,
-    gpu_images: Res<RenderAssets<Image>>,
-    mut genderfluid_image: ResMut<GenderfluidImage>,
-    render_device: Res<RenderDevice>,
-) {
-    let gfi = &mut *genderfluid_image;
-    std::mem::swap(&mut gfi.height1, &mut gfi.height2);
-
-    let height1 = &gpu_images[&genderfluid_image.height1];
-    let height2 = &gpu_images[&genderfluid_image.height2];
-    let velocity = &gpu_images[&genderfluid_image.velocity];
-    let terrain_height = &gpu_images[&genderfluid_image.terrain_height];
-
-    let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
-        label: None,
-        layout: &pipeline.texture_bind_group_layout,
-        entries: &[
-            BindGroupEntry {
-                binding: 0,
-                resource: BindingResource::TextureView(&height1.texture_view),
-            },
-            BindGroupEntry {
-                binding: 1,
-                resource: BindingResource::TextureView(&height2.texture_view),
-            },
-            BindGroupEntry {
-                binding: 2,
-                resource: BindingResource::TextureView(&velocity.texture_view),
-            },
-            BindGroupEntry {
-                binding: 3,
-                resource: BindingResource::TextureView(&terrain_height.texture_view),
-            },
-            BindGroupEntry {
-                binding: 4,
-                resource: genderfluid_image.uniforms.as_entire_binding(),
-            },
-        ],
-    });
-    commands.insert_resource(GenderfluidImageBindGroup(bind_group));
-}
-
-#[derive(Resource)]
-pub struct GenderfluidPipeline {
-    texture_bind_group_layout: BindGroupLayout,
-    init_pipeline: CachedComputePipelineId,
-    update_pipeline: CachedComputePipelineId,
-}
-
-impl FromWorld for GenderfluidPipeline {
-    fn from_world(world: &mut World) -> Self {
-        let make_binding = |binding: u32, access: StorageTextureAccess| BindGroupLayoutEntry {
-            binding,
-            visibility: ShaderStages::COMPUTE,
-            ty: BindingType::StorageTexture {
-                access,
-                format: TextureFormat::R32Float,
-                view_dimension: TextureViewDimension::D2,
-            },
-            count: None,
-        };
-        let texture_bind_group_layout =
-            world
-                .resource::<RenderDevice>()
-                .create_bind_group_layout(&BindGroupLayoutDescriptor {
-                    label: None,
-                    entries: &[
-                        // height_in
-                        make_binding(0, StorageTextureAccess::ReadOnly),
-                        // height_out
-                        make_binding(1, StorageTextureAccess::WriteOnly),
-                        // velocity
-                        make_binding(2, StorageTextureAccess::ReadWrite),
-                        // terrain_height_in
-                        make_binding(3, StorageTextureAccess::ReadWrite),
-                        // uniforms
-                        BindGroupLayoutEntry {
-                            binding: 4,
-                            visibility: ShaderStages::COMPUTE,
-                            ty: BindingType::Buffer {
-                                ty: BufferBindingType::Uniform,
-                                has_dynamic_offset: false,
-                                min_binding_size: BufferSize::new(std::mem::size_of::<
-                                    FluidComputeUniforms,
-                                >(
-                                )
-                                    as u64),
-                            },
-                            count: None,
-                        },
-                    ],
-                });
-        let shader = world
-            .resource::<AssetServer>()
-            .load("shaders/fluid_heightmap_berechnungsschattierer.wgsl");
-        let pipeline_cache = world.resource::<PipelineCache>();
-        let init_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
-            label: None,
-            layout: vec![texture_bind_group_layout.clone()],
-            push_constant_ranges: Vec::new(),
-            shader: shader.clone(),
-            shader_defs: vec![],
-            entry_point: Cow::from("init"),
-        });
-        let update_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
-            label: None,
-            layout: vec![texture_bind_group_layout.clone()],
-            push_constant_ranges: Vec::new(),
-            shader,
-            shader_defs: vec![],
-            entry_point: Cow::from("update"),
-        });
-
-        GenderfluidPipeline {
-            texture_bind_group_layout,
-            init_pipeline,
-            update_pipeline,
-        }
-    }
-}
-
-enum GenderfluidState {
-    Loading,
-    Init,
-    Update,
-}
-
-struct GenderfluidNode {
-    state: GenderfluidState,
-}
-
-impl Default for GenderfluidNode {
-    fn default() -> Self {
-        Self {
-            state: GenderfluidState::Loading,
-        }
-    }
-}
-
-impl render_graph::Node for GenderfluidNode {
-    fn update(&mut self, world: &mut World) {
-        let pipeline = world.resource::<GenderfluidPipeline>();
-        let pipeline_cache = world.resource::<PipelineCache>();
-
-        // if the corresponding pipeline has loaded, transition to the next stage
-        match self.state {
-            GenderfluidState::Loading => {
-                if let CachedPipelineState::Ok(_) =
-                    pipeline_cache.get_compute_pipeline_state(pipeline.init_pipeline)
-                {
-                    self.state = GenderfluidState::Init;
-                }
-            }
-            GenderfluidState::Init => {
-                if let CachedPipelineState::Ok(_) =
-                    pipeline_cache.get_compute_pipeline_state(pipeline.update_pipeline)
-                {
-                    self.state = GenderfluidState::Update;
-                }
-            }
-            GenderfluidState::Update => {}
-        }
-    }
-
-    fn run(
-        &self,
-        _graph: &mut render_graph::RenderGraphContext,
-        render_context: &mut RenderContext,
-        world: &World,
-    ) -> Result<(), render_graph::NodeRunError> {
-        let texture_bind_group = &world.resource::<GenderfluidImageBindGroup>().0;
-        let pipeline_cache = world.resource::<PipelineCache>();
-        let pipeline = world.resource::<GenderfluidPipeline>();
-
-        let mut pass = render_context
-            .command_encoder()
-            .begin_compute_pass(&ComputePassDescriptor::default());
-
-        pass.set_bind_group(0, texture_bind_group, &[]);
-
-        // select the pipeline based on the current state
-        match self.state {
-            GenderfluidState::Loading => {}
-            GenderfluidState::Init => {
-                let init_pipeline = pipeline_cache
-                    .get_compute_pipeline(pipeline.init_pipeline)
-                    .unwrap();
-                pass.set_pipeline(init_pipeline);
-                pass.dispatch_workgroups(SIZE / WORKGROUP_SIZE, SIZE / WORKGROUP_SIZE, 1);
-            }
-            GenderfluidState::Update => {
-                let update_pipeline = pipeline_cache
-                    .get_compute_pipeline(pipeline.update_pipeline)
-                    .unwrap();
-                pass.set_pipeline(update_pipeline);
-                pass.dispatch_workgroups(SIZE / WORKGROUP_SIZE, SIZE / WORKGROUP_SIZE, 1);
-            }
-        }
-
-        Ok(())
-    }
-}
-
-// Add this system to handle sphere input
-pub fn sphere_input_map(
-    mut events: EventWriter<SphereControlEvent>,
-    keyboard: Res<Input<KeyCode>>,
-    controllers: Query<&SphereController>,
-    player: Query<&Transform, With<Player>>,
-    camera: Query<&Transform, With<OrbitCameraController>>,
-) {
-    // Can only control one sphere at a time.
-    let controller = if let Some(controller) = controllers.iter().find(|c| c.enabled) {
-        controller
-    } else {
-        return;
-    };
-
-    let SphereController {
-        translate_sensitivity,
-        ..
-    } = *controller;
-
-    let mut view_direction = player.single().translation - camera.single().translation;
-    view_direction.y = 0.0;
-    view_direction = view_direction.normalize();
-
-    let left = Transform::from_rotation(Quat::from_euler(EulerRot::XYZ, 0.0, PI / 2.0, 0.0))
-        * view_direction;
-
-    for (key, dir) in [
-        (KeyCode::W, view_direction),
-        (KeyCode::A, left),
-        (KeyCode::S, -view_direction),
-        (KeyCode::D, -left),
-    ]
-    .iter()
-    .cloned()
-    {
-        if keyboard.pressed(key) {
-            events.send(SphereControlEvent::Translate(translate_sensitivity * dir));
-        }
-    }
-}
+//! A compute shader that simulates Genderfluid.
+//!
+//! Compute shaders use the GPU for computing arbitrary information, that may be independent of what
+//! is rendered to the screen.
+
+mod debug_view;
+mod extract_heights;
+mod fluid_boundary;
+mod fluid_chunks;
+mod fluid_diagnostics;
+mod fluid_domain;
+mod fluid_erosion;
+mod fluid_tiles;
+mod inspector;
+mod orbit_camera;
+mod pipeline_errors;
+mod plant_instancing;
+mod scene_color;
+mod terrain_generator;
+mod terrain_material;
+mod water_material;
+use bevy::{
+    core::{Pod, Zeroable},
+    core_pipeline::prepass::DepthPrepass,
+    gltf::Gltf,
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::RenderAssets,
+        render_graph::{self, RenderGraph},
+        render_resource::*,
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        view::NoFrustumCulling,
+        Render, RenderApp, RenderSet,
+    },
+    utils::HashMap,
+    window::WindowPlugin,
+};
+use bevy_shader_utils::ShaderUtilsPlugin;
+use debug_view::{DebugView, DebugViewPlugin};
+use extract_heights::{
+    ChunkPlants, GenderfluidExtractNode, GenderfluidExtractPipeline, GenderfluidImage,
+    QueryPosition,
+};
+use fluid_chunks::{
+    spawn_fluid_chunks, ChunkState, FluidChunk, FluidChunkFeedback, FluidChunkPlugin,
+    ResetSimulation,
+};
+use fluid_diagnostics::{FluidDiagnosticsPlugin, PassTimestamps, TimedPass};
+use fluid_domain::FluidDomain;
+use fluid_erosion::{ErosionSettings, FluidErosionPlugin};
+use fluid_tiles::FluidTilePlugin;
+use inspector::{inspector_closed, InspectorPlugin};
+use orbit_camera::{ControlEvent, OrbitCameraBundle, OrbitCameraController, OrbitCameraPlugin};
+use pipeline_errors::PipelineErrorPlugin;
+use plant_instancing::{PlantInstances, PlantInstancingPlugin};
+use rand::Rng;
+use scene_color::SceneColorPlugin;
+use smooth_bevy_cameras::LookTransformPlugin;
+use std::{
+    borrow::Cow, char::from_u32_unchecked, f32::consts::PI, mem::size_of, ops::Deref, sync::Arc,
+};
+use terrain_generator::{TerrainGenerator, TerrainGeneratorPlugin, TerrainGeneratorUniform};
+use terrain_material::TerrainMaterial;
+use water_material::WaterMaterial;
+use wgpu::Maintain;
+
+const SIZE: u32 = 256;
+const WORKGROUP_SIZE: u32 = 8;
+const CELL_SIZE: u32 = 32;
+
+// Define a struct to keep some information about our entity.
+// Here it's an arbitrary movement speed, the spawn location, and a maximum distance from it.
+#[derive(Component)]
+struct Movable {
+    spawn: Vec3,
+    max_distance: f32,
+    speed: f32,
+}
+
+// Implement a utility function for easier Movable struct creation.
+impl Movable {
+    fn new(spawn: Vec3) -> Self {
+        Movable {
+            spawn,
+            max_distance: 5.0,
+            speed: 2.0,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Plant {
+    pub health: f32,
+    /// How far the plant has grown towards its full size, in `0.0..=1.0`.
+    pub growth: f32,
+    /// Accumulated drought or flood stress, the plant starts wilting once this reaches `1.0`.
+    pub stress: f32,
+    pub is_no_longer_baby: bool,
+	pub was_se_fuer_ne_zelle_is: (usize, usize),
+}
+
+/// Growth parameters shared by all plants.
+///
+/// Rates are per second, so plants react to sustained conditions instead of the current frame.
+/// Moisture values are the saturation of the soil underneath the plant, in `0.0..=1.0`.
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct PlantSpecies {
+    /// Soil moisture below which the plant counts as being in a drought.
+    pub drought_threshold: f32,
+    /// Soil moisture above which the plant counts as being flooded.
+    pub flood_threshold: f32,
+    /// Seconds of continuous drought until the plant starts wilting.
+    pub drought_tolerance: f32,
+    /// Seconds of continuous flooding until the plant starts wilting.
+    pub flood_tolerance: f32,
+    /// How fast stress wears off again under good conditions.
+    pub recovery_rate: f32,
+    /// How fast health follows the water conditions.
+    pub regeneration_rate: f32,
+    /// How fast health drops once the plant is stressed out.
+    pub wilt_rate: f32,
+    /// How fast a healthy plant grows to full size.
+    pub growth_rate: f32,
+    /// Scale of a fully grown plant.
+    pub max_scale: f32,
+    /// Water volume a fully grown, healthy plant draws from the simulation per second, from the
+    /// soil moisture first and from the surface water once the soil runs dry.
+    pub water_uptake: f32,
+    /// Radius in texels over which the uptake is spread.
+    pub root_radius: u32,
+    /// How much a fully grown plant shades the ground and slows evaporation, in `0.0..=1.0`.
+    pub transpiration_shade: f32,
+    /// Chance per second that an empty, perfectly suitable grid cell gets colonised.
+    pub colonization_rate: f32,
+    /// Tint of a plant at full health.
+    pub healthy_tint: Color,
+    /// Tint of a plant that is about to die.
+    pub wilted_tint: Color,
+}
+
+impl Default for PlantSpecies {
+    fn default() -> Self {
+        Self {
+            drought_threshold: 0.2,
+            flood_threshold: 0.95,
+            drought_tolerance: 4.0,
+            flood_tolerance: 2.0,
+            recovery_rate: 0.5,
+            regeneration_rate: 0.5,
+            wilt_rate: 0.25,
+            growth_rate: 0.2,
+            max_scale: 0.05,
+            water_uptake: 0.5,
+            root_radius: 3,
+            transpiration_shade: 0.5,
+            colonization_rate: 0.2,
+            healthy_tint: Color::WHITE,
+            wilted_tint: Color::rgb(0.45, 0.3, 0.12),
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct PlantGrid {
+    pub grid: Vec<Vec<Option<Entity>>>,
+}
+
+#[derive(Resource)]
+struct PlantAsset(Handle<Gltf>);
+
+/// The spots looked up for the plant cells of an awake chunk, in the order of its query buffer.
+struct QueriedCells {
+    spots: Vec<Vec3>,
+    /// Whether the player was inside the chunk, their lookup follows the spots.
+    player: bool,
+}
+
+/// What [`update_plants`] asked the `extract` pass to look up in each awake chunk, read back the
+/// frame after.
+#[derive(Resource, Default)]
+struct PlantQueries(HashMap<Entity, QueriedCells>);
+
+/// The chunks whose lookups are being read back this frame.
+#[derive(Resource, Default)]
+struct PendingPlantQueries(Vec<Entity>);
+
+impl FromWorld for PlantGrid {
+    fn from_world(world: &mut World) -> Self {
+        let cells = world.resource::<FluidDomain>().cell_count();
+        Self {
+            grid: vec![vec![None; cells.y as usize]; cells.x as usize],
+        }
+    }
+}
+
+#[derive(Default, Component)]
+pub struct SphereController {
+    pub enabled: bool,
+    pub translate_sensitivity: f32,
+}
+
+#[derive(Event)]
+pub enum SphereControlEvent {
+    Translate(Vec3),
+}
+
+/// Systems reading buffers back from the GPU, the copies are queued in `Copy` and mapped by the
+/// single blocking poll of `wait_for_readback` before `Read` runs.
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GpuReadback {
+    Copy,
+    Read,
+}
+
+// const ATTRIBUTE_BLEND_COLOR: MeshVertexAttribute =
+//     MeshVertexAttribute::new("BlendColor", 988540917, VertexFormat::Float32x4);
+
+// A simple 3D scene with light shining over a cube sitting on a plane.
+fn main() {
+    let default_plugins = DefaultPlugins.set(WindowPlugin {
+        primary_window: Some(Window {
+            // uncomment for unthrottled FPS
+            // present_mode: bevy::window::PresentMode::AutoNoVsync,
+            ..default()
+        }),
+        ..default()
+    });
+    // edited shaders get recompiled, the simulation pauses meanwhile
+    #[cfg(feature = "hot_reload")]
+    let default_plugins = default_plugins.set(AssetPlugin {
+        watch_for_changes: bevy::asset::ChangeWatcher::with_delay(
+            std::time::Duration::from_millis(200),
+        ),
+        ..default()
+    });
+
+    App::new()
+        .add_plugins((
+            default_plugins,
+            GenderfluidComputePlugin,
+            FluidChunkPlugin,
+            FluidTilePlugin,
+            FluidErosionPlugin,
+            TerrainGeneratorPlugin,
+            SceneColorPlugin,
+            OrbitCameraPlugin::default(),
+            LookTransformPlugin,
+            ShaderUtilsPlugin,
+            MaterialPlugin::<WaterMaterial>::default(),
+            MaterialPlugin::<TerrainMaterial>::default(),
+            PlantInstancingPlugin,
+        ))
+        // debugging tools
+        .add_plugins((
+            PipelineErrorPlugin,
+            FluidDiagnosticsPlugin::default(),
+            DebugViewPlugin,
+            InspectorPlugin,
+        ))
+        .add_event::<SphereControlEvent>()
+        .init_resource::<PlantGrid>()
+        .init_resource::<PlantQueries>()
+        .init_resource::<PendingPlantQueries>()
+        .register_type::<PlantSpecies>()
+        .init_resource::<PlantSpecies>()
+        .add_systems(Startup, (spawn_fluid_chunks, apply_deferred, setup).chain())
+        .add_systems(
+            Update,
+            (
+                sphere_input_map,
+                move_sphere,
+                cursor_grab_system.run_if(inspector_closed),
+                update_camera_target,
+                update_plant_health,
+                reset_plants,
+            ),
+        )
+        .add_systems(
+            PostUpdate,
+            (
+                write_fluid_compute_uniforms,
+                copy_back_plant_queries.in_set(GpuReadback::Copy),
+                wait_for_readback,
+                update_plants.in_set(GpuReadback::Read),
+                write_plant_water_sinks.after(update_plants),
+            ),
+        )
+        .configure_sets(
+            PostUpdate,
+            (
+                GpuReadback::Copy.before(wait_for_readback),
+                GpuReadback::Read.after(wait_for_readback),
+            ),
+        )
+        .run();
+}
+
+use bevy::window::CursorGrabMode;
+
+fn cursor_grab_system(
+    mut windows: Query<&mut Window>,
+    btn: Res<Input<MouseButton>>,
+    key: Res<Input<KeyCode>>,
+) {
+    let mut window = windows.single_mut();
+
+    if btn.just_pressed(MouseButton::Left) {
+        window.cursor.grab_mode = CursorGrabMode::Locked;
+        window.cursor.visible = false;
+    }
+
+    if key.just_pressed(KeyCode::Escape) {
+        window.cursor.grab_mode = CursorGrabMode::None;
+        window.cursor.visible = true;
+    }
+}
+
+/// Clears the plants away on [`ResetSimulation`], the ground they grew on is generated anew.
+fn reset_plants(
+    mut commands: Commands,
+    mut resets: EventReader<ResetSimulation>,
+    mut plant_grid: ResMut<PlantGrid>,
+    plants: Query<Entity, With<Plant>>,
+) {
+    if resets.is_empty() {
+        return;
+    }
+    resets.clear();
+    for plant in &plants {
+        commands.entity(plant).despawn_recursive();
+    }
+    for column in &mut plant_grid.grid {
+        column.fill(None);
+    }
+}
+
+fn update_camera_target(
+    mut events: EventWriter<ControlEvent>,
+    player: Query<&Transform, With<Player>>,
+) {
+    events.send(ControlEvent::NewTarget(player.single().translation));
+}
+
+#[derive(Resource, Reflect, Debug, Clone, TypeUuid, ShaderType, Pod, Zeroable, Copy)]
+#[repr(C)]
+#[uuid = "61e3fe7d-e307-4d7f-a060-35fff2cba963"]
+struct FluidComputeUniforms {
+    player_position: Vec2,
+    click: u32,
+    /// World size of a texel, the domain is square.
+    texel_size: f32,
+    /// Position of the chunk's first texel in the whole domain.
+    texel_offset: IVec2,
+    /// Which neighbouring chunks exchange edges, see [`FluidChunk::neighbour_mask`].
+    neighbour_mask: u32,
+    _padding: u32,
+    /// [`crate::fluid_boundary::BoundaryCondition::kind`] of the west, east, south and north sides.
+    boundary_kinds: UVec4,
+    /// Water surface height held outside of the inflow sides.
+    inflow_levels: Vec4,
+    /// See [`ErosionSettings`].
+    sediment_capacity: f32,
+    erosion_rate: f32,
+    deposition_rate: f32,
+    min_slope: f32,
+    talus_slope: f32,
+    /// Zero while thermal erosion is off.
+    thermal_rate: f32,
+    /// See [`DebugView`], the field as its index.
+    debug_field: u32,
+    debug_min: f32,
+    debug_max: f32,
+    _padding_2: [u32; 3],
+    /// What the `init` pass generates the terrain from.
+    generator: TerrainGeneratorUniform,
+}
+
+// fn spawn_plants(
+//     mut commands: Commands,
+//     time: Res<Time>,
+//     mut plant_grid: ResMut<PlantGrid>,
+//     plant_asset: Res<PlantAsset>,
+// ) {
+//     // Some condition to control when to spawn a new plant, e.g., every second
+//     static mut LAST_SPAWN_TIME: f64 = 0.0;
+//     let current_time = time.elapsed_seconds_f64();
+//     if current_time - unsafe { LAST_SPAWN_TIME } < 0.015 {
+//         return;
+//     }
+//     unsafe {
+//         LAST_SPAWN_TIME = current_time;
+//     }
+
+//     let mut rng = rand::thread_rng();
+//     use rand::Rng;
+
+//     // Loop through the grid to find an empty spot to place a new plant
+//     for i in 0..(SIZE / CELL_SIZE) {
+//         for j in 0..(SIZE / CELL_SIZE) {
+//             if plant_grid.grid[i as usize][j as usize].is_none() {
+//                 let offset_x: f32 = rng.gen_range(0.0..=CELL_SIZE as f32);
+//                 let offset_z: f32 = rng.gen_range(0.0..=CELL_SIZE as f32);
+
+//                 let world_x = i as f32 * CELL_SIZE as f32 + offset_x - SIZE as f32 / 2.0;
+//                 let world_z = j as f32 * CELL_SIZE as f32 + offset_z - SIZE as f32 / 2.0;
+
+//                 // Spawn a new plant entity
+//                 let new_plant = commands
+//                     .spawn(SceneBundle {
+//                         scene: plant_asset.0.clone(),
+//                         transform: Transform::from_xyz(world_x * 0.02, 2.0, world_z * 0.02)
+//                             .with_scale(Vec3::splat(0.02)),
+//                         ..Default::default()
+//                     })
+//                     .insert(Plant::default())
+//                     .id();
+
+//                 // Update the grid
+//                 plant_grid.grid[i as usize][j as usize] = Some(new_plant);
+
+//                 // Possibly break if you only want to spawn one plant per update
+//                 return;
+//             }
+//         }
+//     }
+// }
+
+fn update_plant_health(
+    time: Res<Time>,
+    mut plant_grid: ResMut<PlantGrid>,
+    mut plants: Query<(&mut Transform, &mut Plant, &mut Visibility)>,
+    mut commands: Commands,
+    plant_asset: Res<PlantAsset>,
+    spheres: Query<&Transform, (With<SphereController>, Without<Plant>)>,
+) {
+    // let ball_transform = spheres.single();
+
+    // let current_time = time.elapsed_seconds() as f32;
+    // let dt = time.delta_seconds() as f32;
+
+
+            // grow_plant_at(
+                // i,
+                // j,
+                // dt,
+                // ball_transform,
+                // &mut plant_grid,
+                // &mut plants,
+                // &mut commands,
+                // &plant_asset,
+            // )
+        // }
+    // }
+}
+
+fn health_curve(x: f32, difficulty: f32) -> f32 {
+    let health_peak_marker = 3.0;
+    1.0 / (1.0 + (x - health_peak_marker).powf(2.0)).powf(difficulty) - 0.1
+}
+
+fn should_spawn_based_on_distance(
+    existing_object_transform: &Transform,
+    spawn_x: f32,
+    spawn_z: f32,
+    max_radius: f32,
+) -> bool {
+    let existing_pos = existing_object_transform.translation;
+    let distance_vec = Vec2::new(spawn_x - existing_pos.x, spawn_z - existing_pos.z);
+    let distance = distance_vec.length();
+
+    // println!("distance: {}", distance);
+
+    if distance > max_radius {
+        return false;
+    }
+
+    let mut rng = rand::thread_rng();
+    let random_val: f32 = rng.gen(); // Generates a float between 0.0 and 1.0
+
+    // Make it more likely to spawn the closer it is to the existing object
+    let probability = 1.0 - (distance / max_radius);
+
+    random_val < probability
+}
+
+fn grow_plant_at(
+    i: u32,
+    j: u32,
+    dt: f32,
+    plant_grid: &PlantGrid,
+	plant: (&mut Transform, &mut Plant, &mut Visibility),
+    species: &PlantSpecies,
+	soil_moisture: f32,
+) {
+    // new plants are spawned by `update_plants`
+    if plant_grid.grid[i as usize][j as usize].is_some() {
+        let (mut transform, mut actual_plant, mut visibility) = plant;
+
+        // Stress builds up while the plant sits outside its comfort zone and wears off otherwise,
+        // so a short splash or dry spell is survived but a long one is not.
+        if soil_moisture < species.drought_threshold {
+            actual_plant.stress += dt / species.drought_tolerance;
+        } else if soil_moisture > species.flood_threshold {
+            actual_plant.stress += dt / species.flood_tolerance;
+        } else {
+            actual_plant.stress -= dt * species.recovery_rate;
+        }
+        actual_plant.stress = actual_plant.stress.clamp(0.0, 1.0);
+
+        let health_rate = if actual_plant.stress >= 1.0 {
+            -species.wilt_rate
+        } else {
+            health_curve(soil_moisture * 5.0, 1.25) * species.regeneration_rate
+        };
+        actual_plant.health = (actual_plant.health + health_rate * dt).clamp(-1.0, 1.0);
+        if actual_plant.health > 0.0 {
+            actual_plant.growth =
+                (actual_plant.growth + species.growth_rate * actual_plant.health * dt).min(1.0);
+        }
+
+        transform.scale = Vec3::splat(actual_plant.growth * species.max_scale);
+        trace!(
+            health = actual_plant.health,
+            stress = actual_plant.stress,
+            growth = actual_plant.growth,
+            "plant grew"
+        );
+        if actual_plant.is_no_longer_baby && actual_plant.health <= 0.0 {
+            *visibility = Visibility::Hidden;
+        } else if !actual_plant.is_no_longer_baby && actual_plant.health > 0.0 {
+            actual_plant.is_no_longer_baby = true;
+        }
+    }
+}
+
+/// set up a simple 3D scene
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut standard_materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+    chunks: Query<&FluidChunk>,
+) {
+    let eye = Vec3::new(-2.0, 5.0, 5.1);
+    let target = Vec3::default();
+    let controllllller = OrbitCameraController::default();
+
+    let plant = asset_server.load("glowingflower2.glb");
+    commands.insert_resource(PlantAsset(plant));
+    // all plants are drawn in one instanced draw call by this entity
+    commands.spawn((
+        PlantInstances::default(),
+        SpatialBundle::INHERITED_IDENTITY,
+        NoFrustumCulling,
+    ));
+    // to position our 3d model, simply use the Transform
+    // in the SceneBundle
+    // commands
+    //     .spawn(SceneBundle {
+    //         scene: plant,
+    //         transform: Transform::from_xyz(0.0, 2.0, -5.0).with_scale(Vec3::splat(0.1)),
+    //         ..Default::default()
+    //     })
+    //     .insert(Plant::default());
+
+    commands
+        .spawn((
+            Camera3dBundle::default(),
+            // the water refracts and reflects the terrain by its depth
+            DepthPrepass,
+        ))
+        .insert(OrbitCameraBundle::new(controllllller, eye, target, Vec3::Y));
+
+    // light
+    commands.spawn(PointLightBundle {
+        point_light: PointLight {
+            intensity: 1500.0,
+            shadows_enabled: true,
+            ..default()
+        },
+        transform: Transform::from_xyz(4.0, 8.0, 4.0),
+        ..default()
+    });
+
+    // Add a cube to visualize translation.
+    let entity_spawn = Vec3::new(0.0, 3.0, 0.0);
+    commands
+        .spawn((
+            PbrBundle {
+                mesh: meshes.add(Mesh::from(shape::UVSphere {
+                    radius: 0.1337,
+                    sectors: 32,
+                    stacks: 32,
+                })),
+                material: standard_materials.add(Color::WHITE.into()),
+                transform: Transform::from_translation(entity_spawn),
+                ..default()
+            },
+            Movable::new(entity_spawn),
+        ))
+        .insert(SphereController {
+            enabled: true,
+            translate_sensitivity: 2.0,
+            ..Default::default()
+        })
+        .insert(Player);
+
+    // the plants are drawn against the terrain of the chunk the player spawns in
+    let focus = chunks
+        .iter()
+        .find(|chunk| chunk.domain.contains_texel(chunk.domain.world_to_texel(entity_spawn)))
+        .or_else(|| chunks.iter().next())
+        .expect("the fluid domain has no chunks");
+    commands.insert_resource(GenderfluidImage {
+        focus: focus.coord,
+        focus_domain: focus.domain,
+        terrain_height: focus.terrain_height.clone(),
+    });
+}
+
+// This system will move all Movable entities with a Transform
+pub fn move_sphere(
+    mut spheres: Query<(&mut Transform, &mut SphereController)>,
+    mut events: EventReader<SphereControlEvent>,
+    timer: Res<Time>,
+) {
+    for (mut transform, controller) in &mut spheres {
+        if !controller.enabled {
+            continue;
+        }
+
+        for event in events.iter() {
+            match event {
+                SphereControlEvent::Translate(dir) => {
+                    let movement = *dir * timer.delta_seconds();
+                    // println!("moving: {}", movement);
+                    transform.translation += movement;
+                }
+            }
+        }
+    }
+}
+
+pub struct GenderfluidComputePlugin;
+
+impl Plugin for GenderfluidComputePlugin {
+    fn build(&self, app: &mut App) {
+        // Extract the genderfluid image resource from the main world into the render world
+        // for operation on by the compute shader and display on the sprite.
+        app.add_plugins(ExtractResourcePlugin::<GenderfluidImage>::default())
+            .register_type::<FluidDomain>()
+            .init_resource::<FluidDomain>();
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(Render, queue_chunk_bind_groups.in_set(RenderSet::Queue));
+        render_app.add_systems(
+            Render,
+            extract_heights::queue_extract_bind_group.in_set(RenderSet::Queue),
+        );
+
+        let mut render_graph = render_app.world.resource_mut::<RenderGraph>();
+        render_graph.add_node("genderfluid", GenderfluidNode::default());
+        render_graph.add_node("genderfluid extract", GenderfluidExtractNode::default());
+        render_graph.add_node_edge("genderfluid", bevy::render::main_graph::node::CAMERA_DRIVER);
+        render_graph.add_node_edge(
+            "genderfluid extract",
+            bevy::render::main_graph::node::CAMERA_DRIVER,
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<GenderfluidPipeline>();
+        render_app.init_resource::<GenderfluidExtractPipeline>();
+    }
+}
+
+#[derive(Component)]
+pub struct Player;
+
+fn write_fluid_compute_uniforms(
+    btn: Res<Input<MouseButton>>,
+    render_queue: Res<RenderQueue>,
+    player: Query<&Transform, With<Player>>,
+    domain: Res<FluidDomain>,
+    erosion: Res<ErosionSettings>,
+    generator: Res<TerrainGenerator>,
+    debug_view: Res<DebugView>,
+    chunks: Query<&FluidChunk>,
+) {
+    let player = player.single().translation;
+    let generator = generator.uniform(&domain);
+    let debug_range = debug_view.range();
+    for chunk in &chunks {
+        if chunk.working_set.is_none() {
+            continue;
+        }
+        render_queue.write_buffer(
+            &chunk.uniforms,
+            0,
+            bevy::core::bytes_of(&FluidComputeUniforms {
+                player_position: chunk.domain.world_to_uv(player),
+                click: btn.pressed(MouseButton::Left) as u32,
+                texel_size: domain.texel_size().x,
+                texel_offset: chunk.texel_offset(),
+                neighbour_mask: chunk.neighbour_mask(),
+                _padding: 0,
+                boundary_kinds: UVec4::from_array(chunk.boundaries.map(|side| side.kind())),
+                inflow_levels: Vec4::from_array(chunk.boundaries.map(|side| side.level())),
+                sediment_capacity: erosion.sediment_capacity,
+                erosion_rate: erosion.erosion_rate,
+                deposition_rate: erosion.deposition_rate,
+                min_slope: erosion.min_slope,
+                talus_slope: erosion.talus_slope,
+                thermal_rate: erosion.current_thermal_rate(),
+                debug_field: debug_view.field as u32,
+                debug_min: debug_range.x,
+                debug_max: debug_range.y,
+                _padding_2: [0; 3],
+                generator,
+            }),
+        );
+    }
+}
+
+/// Queues the copies of what the `extract` pass looked up in the awake chunks last frame.
+fn copy_back_plant_queries(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    queries: Res<PlantQueries>,
+    chunks: Query<(Entity, &FluidChunk)>,
+    mut pending: ResMut<PendingPlantQueries>,
+) {
+    // the plants of sleeping chunks don't grow
+    pending.0 = chunks
+        .iter()
+        .filter(|(entity, chunk)| {
+            chunk.state == ChunkState::Awake && queries.0.contains_key(entity)
+        })
+        .map(|(entity, _)| entity)
+        .collect();
+    if pending.0.is_empty() {
+        return;
+    }
+
+    let mut command_encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("extract heights copy back"),
+    });
+    for (_, chunk) in chunks.iter_many(&pending.0) {
+        let size = (ChunkPlants::queries(&chunk.domain) * size_of::<f32>()) as u64;
+        let plants = &chunk.plants;
+        for (extracted, mapped) in plants.extracted.iter().zip(&plants.extracted_mapped) {
+            command_encoder.copy_buffer_to_buffer(extracted, 0, mapped, 0, size);
+        }
+    }
+    render_queue.submit([command_encoder.finish()]);
+    for (_, chunk) in chunks.iter_many(&pending.0) {
+        for mapped in &chunk.plants.extracted_mapped {
+            mapped.slice(..).map_async(MapMode::Read, |_| {});
+        }
+    }
+}
+
+/// Blocks until the buffers queued in [`GpuReadback::Copy`] are mapped.
+fn wait_for_readback(render_device: Res<RenderDevice>) {
+    render_device.poll(Maintain::Wait);
+}
+
+/// Grows the plants of every awake chunk from what [`copy_back_plant_queries`] read back, spawns
+/// new ones on suitable cells, lets the player follow the water surface and queues the lookups
+/// for the next frame.
+#[allow(clippy::too_many_arguments)]
+fn update_plants(
+    render_queue: Res<RenderQueue>,
+    time: Res<Time>,
+    species: Res<PlantSpecies>,
+    mut commands: Commands,
+    mut queries: ResMut<PlantQueries>,
+    mut pending: ResMut<PendingPlantQueries>,
+    mut plant_grid: ResMut<PlantGrid>,
+    mut player: Query<&mut Transform, (With<Player>, Without<Plant>)>,
+    mut plants: Query<(&mut Transform, &mut Plant, &mut Visibility), Without<Player>>,
+    chunks: Query<(Entity, &FluidChunk)>,
+) {
+    let dt = time.delta_seconds();
+    // water height, terrain height, soil moisture and suitability of each queried texel
+    let mut extracted = HashMap::new();
+    for (entity, chunk) in chunks.iter_many(pending.0.drain(..)) {
+        let read = |mapped: &Buffer| -> Vec<f32> {
+            let values = bytemuck::cast_slice(&mapped.slice(..).get_mapped_range()[..]).to_vec();
+            mapped.unmap();
+            values
+        };
+        let [height, terrain_height, soil_moisture, suitability] =
+            chunk.plants.extracted_mapped.each_ref().map(read);
+        extracted.insert(entity, (height, terrain_height, soil_moisture, suitability));
+    }
+
+    let previous = std::mem::take(&mut queries.0);
+    let mut rng = rand::thread_rng();
+    for (entity, chunk) in &chunks {
+        if chunk.state != ChunkState::Awake {
+            continue;
+        }
+        let extracted = previous.get(&entity).zip(extracted.get(&entity));
+
+        if let Some((queried, (height, terrain_height, _, _))) = extracted {
+            if queried.player {
+                let mut player = player.single_mut();
+                let last = queried.spots.len();
+                let new_y = height[last] + terrain_height[last];
+                if new_y.is_finite() && new_y.abs() < 10.0 {
+                    trace!(
+                        from = player.translation.y,
+                        to = new_y,
+                        water = height[last],
+                        "player follows the surface"
+                    );
+                    player.translation.y = new_y + 0.1337;
+                }
+            }
+        }
+
+        // every cell is looked up, at its plant or at a spot a new one could grow at
+        let cells = chunk.domain.cell_count();
+        let first_cell = chunk.coord * cells;
+        let mut spots = Vec::with_capacity((cells.x * cells.y) as usize);
+        for j in 0..cells.y {
+            for i in 0..cells.x {
+                let k = spots.len();
+                let cell = first_cell + UVec2::new(i, j);
+                let occupant = plant_grid.grid[cell.x as usize][cell.y as usize];
+                if let Some(plant) = occupant.and_then(|plant| plants.get_mut(plant).ok()) {
+                    let (mut transform, mut plant, mut visibility) = plant;
+                    if let Some((_, (_, terrain_height, soil_moisture, _))) = extracted {
+                        transform.translation.y = terrain_height[k];
+                        grow_plant_at(
+                            cell.x,
+                            cell.y,
+                            dt,
+                            &plant_grid,
+                            (&mut *transform, &mut *plant, &mut *visibility),
+                            &species,
+                            soil_moisture[k],
+                        );
+                    }
+                    spots.push(transform.translation);
+                    continue;
+                }
+                if let Some((queried, (_, terrain_height, _, suitability))) = extracted {
+                    if occupant.is_none()
+                        && rng.gen::<f32>() < suitability[k] * species.colonization_rate * dt
+                    {
+                        let spot = queried.spots[k];
+                        let new_plant = commands
+                            .spawn(SpatialBundle {
+                                transform: Transform::from_xyz(spot.x, terrain_height[k], spot.z)
+                                    .with_scale(Vec3::splat(0.0)),
+                                ..Default::default()
+                            })
+                            .insert(Plant {
+                                health: 0.0,
+                                growth: 0.0,
+                                stress: 0.0,
+                                is_no_longer_baby: false,
+                                was_se_fuer_ne_zelle_is: (cell.x as usize, cell.y as usize),
+                            })
+                            .id();
+                        plant_grid.grid[cell.x as usize][cell.y as usize] = Some(new_plant);
+                        spots.push(spot);
+                        continue;
+                    }
+                }
+                let offset = Vec2::new(rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+                spots.push(chunk.domain.cell_to_world(UVec2::new(i, j), offset));
+            }
+        }
+
+        let player_position = player.single().translation;
+        let positions: Vec<QueryPosition> = spots
+            .iter()
+            .chain([&player_position])
+            .map(|spot| chunk.domain.world_to_texel(*spot).into())
+            .collect();
+        render_queue.write_buffer(
+            &chunk.plants.positions,
+            0,
+            bevy::core::cast_slice(&positions),
+        );
+        queries.0.insert(
+            entity,
+            QueriedCells {
+                spots,
+                player: chunk
+                    .domain
+                    .contains_texel(chunk.domain.world_to_texel(player_position)),
+            },
+        );
+    }
+}
+
+/// Uploads how much water the plants draw from each cell (`r`) and how much they shade it
+/// against evaporation (`g`), for the fluid compute pass of each simulated chunk to apply.
+fn write_plant_water_sinks(
+    render_queue: Res<RenderQueue>,
+    chunks: Query<&FluidChunk>,
+    plants: Query<(&Transform, &Plant, &Visibility)>,
+    species: Res<PlantSpecies>,
+) {
+    for chunk in &chunks {
+        if chunk.working_set.is_some() {
+            write_chunk_plant_water(&render_queue, chunk, &plants, &species);
+        }
+    }
+}
+
+fn write_chunk_plant_water(
+    render_queue: &RenderQueue,
+    chunk: &FluidChunk,
+    plants: &Query<(&Transform, &Plant, &Visibility)>,
+    species: &PlantSpecies,
+) {
+    let domain = chunk.domain;
+    // plants next to the chunk draw from it with the roots reaching over the edge
+    let mut sinks = vec![[0.0f32; 2]; (domain.resolution.x * domain.resolution.y) as usize];
+    let radius = species.root_radius as i32;
+    for (transform, plant, visibility) in plants {
+        if *visibility == Visibility::Hidden || plant.health <= 0.0 {
+            continue;
+        }
+        let center = domain.world_to_texel(transform.translation);
+        let mut footprint = vec![];
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if dx * dx + dy * dy > radius * radius {
+                    continue;
+                }
+                if let Some(index) = domain.texel_index(center + IVec2::new(dx, dy)) {
+                    footprint.push(index);
+                }
+            }
+        }
+        if footprint.is_empty() {
+            continue;
+        }
+
+        // Spread the uptake over the roots so the plant drains the same volume regardless of
+        // its footprint.
+        let uptake = species.water_uptake * plant.growth * plant.health / footprint.len() as f32;
+        let shade = plant.growth * species.transpiration_shade;
+        for index in footprint {
+            sinks[index][0] += uptake;
+            sinks[index][1] = (sinks[index][1] + shade).min(1.0);
+        }
+    }
+
+    render_queue.write_texture(
+        ImageCopyTexture {
+            texture: &chunk.plants.water,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        bevy::core::cast_slice(&sinks),
+        ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(domain.resolution.x * size_of::<[f32; 2]>() as u32),
+            rows_per_image: None,
+        },
+        Extent3d {
+            width: domain.resolution.x,
+            height: domain.resolution.y,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+/// The bind groups of one chunk, for the passes its state asks for.
+struct ChunkBindGroups {
+    coord: UVec2,
+    state: ChunkState,
+    workgroups: UVec2,
+    size: Extent3d,
+    textures: BindGroup,
+    export: BindGroup,
+    halo: BindGroup,
+    tile_bounds: BindGroup,
+    tiles: UVec2,
+    packed: Option<BindGroup>,
+    /// Textures the passes write, each with the texture they read that it's copied back onto.
+    copies: [(Texture, Texture); 4],
+}
+
+/// Every chunk with working textures, rebuilt each frame.
+#[derive(Resource)]
+struct FluidChunkBindGroups(Vec<ChunkBindGroups>);
+
+fn queue_chunk_bind_groups(
+    mut commands: Commands,
+    pipeline: Res<GenderfluidPipeline>,
+    gpu_images: Res<RenderAssets<Image>>,
+    chunks: Query<&FluidChunk>,
+    render_device: Res<RenderDevice>,
+) {
+    let mut bind_groups = vec![];
+    for chunk in &chunks {
+        let Some(working_set) = &chunk.working_set else {
+            continue;
+        };
+        // textures of a chunk that just woke up take a frame to reach the GPU
+        let (
+            Some(height),
+            Some(height_out),
+            Some(velocity),
+            Some(terrain_height),
+            Some(soil_moisture),
+            Some(soil_moisture_out),
+            Some(suitability),
+            Some(terrain_normal),
+            Some(water_normal),
+            Some(foam),
+            Some(foam_out),
+            Some(caustics),
+            Some(wetness),
+            Some(sediment),
+            Some(sediment_out),
+            Some(erosion),
+            Some(debug),
+        ) = (
+            gpu_images.get(&chunk.height),
+            gpu_images.get(&working_set.height_out),
+            gpu_images.get(&working_set.velocity),
+            gpu_images.get(&chunk.terrain_height),
+            gpu_images.get(&working_set.soil_moisture),
+            gpu_images.get(&working_set.soil_moisture_out),
+            gpu_images.get(&working_set.suitability),
+            gpu_images.get(&chunk.terrain_normal),
+            gpu_images.get(&chunk.water_normal),
+            gpu_images.get(&chunk.foam),
+            gpu_images.get(&working_set.foam_out),
+            gpu_images.get(&chunk.caustics),
+            gpu_images.get(&chunk.wetness),
+            gpu_images.get(&working_set.sediment),
+            gpu_images.get(&working_set.sediment_out),
+            gpu_images.get(&working_set.erosion),
+            gpu_images.get(&chunk.debug),
+        )
+        else {
+            continue;
+        };
+        let textures = render_device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.texture_bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&height.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&height_out.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&velocity.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(&terrain_height.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: chunk.uniforms.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::TextureView(&chunk.plants.water_view),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::TextureView(&soil_moisture.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: BindingResource::TextureView(&soil_moisture_out.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 8,
+                    resource: BindingResource::TextureView(&suitability.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 9,
+                    resource: BindingResource::TextureView(&terrain_normal.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 10,
+                    resource: BindingResource::TextureView(&water_normal.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 11,
+                    resource: BindingResource::TextureView(&foam.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 12,
+                    resource: BindingResource::TextureView(&foam_out.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 13,
+                    resource: BindingResource::TextureView(&caustics.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 14,
+                    resource: BindingResource::TextureView(&wetness.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 15,
+                    resource: BindingResource::TextureView(&sediment.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 16,
+                    resource: BindingResource::TextureView(&sediment_out.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 17,
+                    resource: BindingResource::TextureView(&erosion.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 18,
+                    resource: BindingResource::TextureView(&debug.texture_view),
+                },
+            ],
+        });
+        let export = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("fluid chunk edges export"),
+            layout: &pipeline.export_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: chunk.edges.as_entire_binding(),
+            }],
+        });
+        let halo_entries: Vec<_> = chunk
+            .neighbour_edges
+            .iter()
+            .enumerate()
+            .map(|(side, edges)| BindGroupEntry {
+                binding: side as u32 + 1,
+                resource: edges
+                    .as_ref()
+                    .unwrap_or(&pipeline.no_neighbour)
+                    .as_entire_binding(),
+            })
+            .collect();
+        let halo = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("fluid chunk halo"),
+            layout: &pipeline.halo_bind_group_layout,
+            entries: &halo_entries,
+        });
+        let tile_bounds = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("fluid chunk tile bounds"),
+            layout: &pipeline.tile_bounds_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 6,
+                resource: chunk.tile_bounds.as_entire_binding(),
+            }],
+        });
+        let packed = chunk.packed.as_ref().map(|packed| {
+            render_device.create_bind_group(&BindGroupDescriptor {
+                label: Some("fluid chunk packed"),
+                layout: &pipeline.packed_bind_group_layout,
+                entries: &[BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::TextureView(&packed.view),
+                }],
+            })
+        });
+
+        bind_groups.push(ChunkBindGroups {
+            coord: chunk.coord,
+            state: chunk.state,
+            workgroups: chunk.domain.resolution / WORKGROUP_SIZE,
+            size: Extent3d {
+                width: chunk.domain.resolution.x,
+                height: chunk.domain.resolution.y,
+                depth_or_array_layers: 1,
+            },
+            textures,
+            export,
+            halo,
+            tile_bounds,
+            tiles: chunk.tiles,
+            packed,
+            copies: [
+                (height_out.texture.clone(), height.texture.clone()),
+                (soil_moisture_out.texture.clone(), soil_moisture.texture.clone()),
+                (foam_out.texture.clone(), foam.texture.clone()),
+                (sediment_out.texture.clone(), sediment.texture.clone()),
+            ],
+        });
+    }
+    commands.insert_resource(FluidChunkBindGroups(bind_groups));
+}
+
+#[derive(Resource)]
+pub struct GenderfluidPipeline {
+    texture_bind_group_layout: BindGroupLayout,
+    export_bind_group_layout: BindGroupLayout,
+    halo_bind_group_layout: BindGroupLayout,
+    packed_bind_group_layout: BindGroupLayout,
+    tile_bounds_bind_group_layout: BindGroupLayout,
+    /// Bound in place of the edges of a missing or sleeping neighbour.
+    no_neighbour: Buffer,
+    init_pipeline: CachedComputePipelineId,
+    update_pipeline: CachedComputePipelineId,
+    suitability_pipeline: CachedComputePipelineId,
+    export_edges_pipeline: CachedComputePipelineId,
+    pack_pipeline: CachedComputePipelineId,
+    unpack_pipeline: CachedComputePipelineId,
+    tile_bounds_pipeline: CachedComputePipelineId,
+    normals_pipeline: CachedComputePipelineId,
+    caustics_pipeline: CachedComputePipelineId,
+    erode_pipeline: CachedComputePipelineId,
+    thermal_pipeline: CachedComputePipelineId,
+    debug_view_pipeline: CachedComputePipelineId,
+}
+
+impl GenderfluidPipeline {
+    /// Every pipeline [`GenderfluidNode`] dispatches, by entry point.
+    pub fn pipelines(&self) -> [(&'static str, CachedComputePipelineId); 12] {
+        [
+            ("init", self.init_pipeline),
+            ("update", self.update_pipeline),
+            ("suitability", self.suitability_pipeline),
+            ("export_edges", self.export_edges_pipeline),
+            ("pack", self.pack_pipeline),
+            ("unpack", self.unpack_pipeline),
+            ("tile_bounds", self.tile_bounds_pipeline),
+            ("normals", self.normals_pipeline),
+            ("caustics", self.caustics_pipeline),
+            ("erode", self.erode_pipeline),
+            ("thermal", self.thermal_pipeline),
+            ("debug_view", self.debug_view_pipeline),
+        ]
+    }
+}
+
+impl FromWorld for GenderfluidPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let make_binding = |binding: u32, access: StorageTextureAccess| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::StorageTexture {
+                access,
+                format: TextureFormat::R32Float,
+                view_dimension: TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let make_normal_binding = |binding: u32| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::StorageTexture {
+                access: StorageTextureAccess::WriteOnly,
+                format: TextureFormat::Rgba16Float,
+                view_dimension: TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let make_edges_binding = |binding: u32, read_only: bool| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let render_device = world.resource::<RenderDevice>();
+        let texture_bind_group_layout =
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    // height_in
+                    make_binding(0, StorageTextureAccess::ReadOnly),
+                    // height_out
+                    make_binding(1, StorageTextureAccess::WriteOnly),
+                    // velocity
+                    make_binding(2, StorageTextureAccess::ReadWrite),
+                    // terrain_height_in
+                    make_binding(3, StorageTextureAccess::ReadWrite),
+                    // uniforms
+                    BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(std::mem::size_of::<
+                                FluidComputeUniforms,
+                            >(
+                            )
+                                as u64),
+                        },
+                        count: None,
+                    },
+                    // plant_water
+                    BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: false },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // soil_moisture_in
+                    make_binding(6, StorageTextureAccess::ReadOnly),
+                    // soil_moisture_out
+                    make_binding(7, StorageTextureAccess::WriteOnly),
+                    // suitability_out
+                    make_binding(8, StorageTextureAccess::WriteOnly),
+                    // terrain_normal_out
+                    make_normal_binding(9),
+                    // water_normal_out
+                    make_normal_binding(10),
+                    // foam_in
+                    make_binding(11, StorageTextureAccess::ReadOnly),
+                    // foam_out
+                    make_binding(12, StorageTextureAccess::WriteOnly),
+                    // caustics_out
+                    make_binding(13, StorageTextureAccess::WriteOnly),
+                    // wetness
+                    make_binding(14, StorageTextureAccess::ReadWrite),
+                    // sediment_in
+                    make_binding(15, StorageTextureAccess::ReadWrite),
+                    // sediment_out
+                    make_binding(16, StorageTextureAccess::WriteOnly),
+                    // erosion
+                    make_binding(17, StorageTextureAccess::ReadWrite),
+                    // debug_out
+                    BindGroupLayoutEntry {
+                        binding: 18,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::StorageTexture {
+                            access: StorageTextureAccess::WriteOnly,
+                            format: TextureFormat::Rgba8Unorm,
+                            view_dimension: TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let export_bind_group_layout =
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("fluid chunk edges export layout"),
+                entries: &[
+                    // edges_out
+                    make_edges_binding(0, false),
+                ],
+            });
+        let halo_bind_group_layout =
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("fluid chunk halo layout"),
+                entries: &[
+                    // halo_west, halo_east, halo_south, halo_north
+                    make_edges_binding(1, true),
+                    make_edges_binding(2, true),
+                    make_edges_binding(3, true),
+                    make_edges_binding(4, true),
+                ],
+            });
+        let packed_bind_group_layout =
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("fluid chunk packed layout"),
+                entries: &[
+                    // packed
+                    BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::StorageTexture {
+                            access: StorageTextureAccess::ReadWrite,
+                            format: TextureFormat::R32Uint,
+                            view_dimension: TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let tile_bounds_bind_group_layout =
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("fluid chunk tile bounds layout"),
+                entries: &[
+                    // tile_bounds_out
+                    make_edges_binding(6, false),
+                ],
+            });
+        let no_neighbour = render_device.create_buffer(&BufferDescriptor {
+            label: Some("fluid chunk no neighbour"),
+            size: std::mem::size_of::<Vec4>() as u64,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let shader = world
+            .resource::<AssetServer>()
+            .load("shaders/fluid_heightmap_berechnungsschattierer.wgsl");
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let queue_pipeline = |entry_point: &'static str, layout: Vec<BindGroupLayout>| {
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: None,
+                layout,
+                push_constant_ranges: Vec::new(),
+                shader: shader.clone(),
+                shader_defs: vec![],
+                entry_point: Cow::from(entry_point),
+            })
+        };
+        let init_pipeline = queue_pipeline("init", vec![texture_bind_group_layout.clone()]);
+        let update_pipeline = queue_pipeline(
+            "update",
+            vec![texture_bind_group_layout.clone(), halo_bind_group_layout.clone()],
+        );
+        let suitability_pipeline = queue_pipeline(
+            "suitability",
+            vec![texture_bind_group_layout.clone(), halo_bind_group_layout.clone()],
+        );
+        let export_edges_pipeline = queue_pipeline(
+            "export_edges",
+            vec![texture_bind_group_layout.clone(), export_bind_group_layout.clone()],
+        );
+        let pack_pipeline = queue_pipeline(
+            "pack",
+            vec![texture_bind_group_layout.clone(), packed_bind_group_layout.clone()],
+        );
+        let unpack_pipeline = queue_pipeline(
+            "unpack",
+            vec![texture_bind_group_layout.clone(), packed_bind_group_layout.clone()],
+        );
+        let normals_pipeline = queue_pipeline(
+            "normals",
+            vec![texture_bind_group_layout.clone(), halo_bind_group_layout.clone()],
+        );
+        let erode_pipeline = queue_pipeline("erode", vec![texture_bind_group_layout.clone()]);
+        let thermal_pipeline = queue_pipeline(
+            "thermal",
+            vec![texture_bind_group_layout.clone(), halo_bind_group_layout.clone()],
+        );
+        let caustics_pipeline = queue_pipeline(
+            "caustics",
+            vec![texture_bind_group_layout.clone(), halo_bind_group_layout.clone()],
+        );
+        let tile_bounds_pipeline = queue_pipeline(
+            "tile_bounds",
+            vec![texture_bind_group_layout.clone(), tile_bounds_bind_group_layout.clone()],
+        );
+        let debug_view_pipeline =
+            queue_pipeline("debug_view", vec![texture_bind_group_layout.clone()]);
+
+        GenderfluidPipeline {
+            texture_bind_group_layout,
+            export_bind_group_layout,
+            halo_bind_group_layout,
+            packed_bind_group_layout,
+            tile_bounds_bind_group_layout,
+            no_neighbour,
+            init_pipeline,
+            update_pipeline,
+            suitability_pipeline,
+            export_edges_pipeline,
+            pack_pipeline,
+            unpack_pipeline,
+            tile_bounds_pipeline,
+            normals_pipeline,
+            caustics_pipeline,
+            erode_pipeline,
+            thermal_pipeline,
+            debug_view_pipeline,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GenderfluidState {
+    Loading,
+    Update,
+    /// A shader changed and its pipelines are being compiled again. The chunks keep their
+    /// textures and pick up where they left off once every pipeline is back.
+    Paused,
+}
+
+struct GenderfluidNode {
+    state: GenderfluidState,
+}
+
+impl Default for GenderfluidNode {
+    fn default() -> Self {
+        Self {
+            state: GenderfluidState::Loading,
+        }
+    }
+}
+
+impl render_graph::Node for GenderfluidNode {
+    fn update(&mut self, world: &mut World) {
+        let pipeline = world.resource::<GenderfluidPipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        // Only simulate while every pipeline is ready. Skipping some of the passes would leave
+        // the textures half updated, and chunks waiting on `init`, `pack` or `unpack` would be
+        // reported as done without it.
+        let ready = pipeline.pipelines().into_iter().all(|(_, id)| {
+            matches!(
+                pipeline_cache.get_compute_pipeline_state(id),
+                CachedPipelineState::Ok(_)
+            )
+        });
+        self.state = match (self.state, ready) {
+            (GenderfluidState::Loading | GenderfluidState::Paused, true) => {
+                if self.state == GenderfluidState::Paused {
+                    info!("fluid pipelines recompiled, resuming the simulation");
+                }
+                GenderfluidState::Update
+            }
+            (GenderfluidState::Update, false) => {
+                info!("fluid pipelines are recompiling, pausing the simulation");
+                GenderfluidState::Paused
+            }
+            (state, _) => state,
+        };
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let GenderfluidState::Update = self.state else {
+            return Ok(());
+        };
+        let Some(FluidChunkBindGroups(chunks)) = world.get_resource::<FluidChunkBindGroups>() else {
+            return Ok(());
+        };
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = world.resource::<GenderfluidPipeline>();
+        let (Some(init), Some(update), Some(export_edges), Some(pack), Some(unpack)) = (
+            pipeline_cache.get_compute_pipeline(pipeline.init_pipeline),
+            pipeline_cache.get_compute_pipeline(pipeline.update_pipeline),
+            pipeline_cache.get_compute_pipeline(pipeline.export_edges_pipeline),
+            pipeline_cache.get_compute_pipeline(pipeline.pack_pipeline),
+            pipeline_cache.get_compute_pipeline(pipeline.unpack_pipeline),
+        ) else {
+            return Ok(());
+        };
+        let awake = || chunks.iter().filter(|chunk| chunk.state == ChunkState::Awake);
+        let timestamps = world.get_resource::<PassTimestamps>();
+
+        let encoder = render_context.command_encoder();
+        if let Some(timestamps) = timestamps {
+            timestamps.start(encoder, TimedPass::Fluid);
+        }
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+
+            // every chunk publishes its edges before any of them reads its neighbours'
+            pass.set_pipeline(export_edges);
+            for chunk in awake() {
+                pass.set_bind_group(0, &chunk.textures, &[]);
+                pass.set_bind_group(1, &chunk.export, &[]);
+                pass.dispatch_workgroups(chunk.workgroups.max_element(), 1, 1);
+            }
+
+            for chunk in chunks {
+                pass.set_bind_group(0, &chunk.textures, &[]);
+                match (chunk.state, &chunk.packed) {
+                    (ChunkState::Generating, _) => pass.set_pipeline(init),
+                    (ChunkState::Awake, _) => {
+                        pass.set_pipeline(update);
+                        pass.set_bind_group(1, &chunk.halo, &[]);
+                    }
+                    (ChunkState::FallingAsleep, Some(packed)) => {
+                        pass.set_pipeline(pack);
+                        pass.set_bind_group(1, packed, &[]);
+                    }
+                    (ChunkState::WakingUp, Some(packed)) => {
+                        pass.set_pipeline(unpack);
+                        pass.set_bind_group(1, packed, &[]);
+                    }
+                    _ => continue,
+                }
+                pass.dispatch_workgroups(chunk.workgroups.x, chunk.workgroups.y, 1);
+            }
+        }
+
+        // the passes read the textures the chunk is drawn from and write scratch ones, copy
+        // the results back over
+        for chunk in chunks
+            .iter()
+            .filter(|chunk| chunk.state != ChunkState::FallingAsleep)
+        {
+            for (written, read) in &chunk.copies {
+                encoder.copy_texture_to_texture(
+                    written.as_image_copy(),
+                    read.as_image_copy(),
+                    chunk.size,
+                );
+            }
+        }
+
+        if let (Some(thermal), Some(erode)) = (
+            pipeline_cache.get_compute_pipeline(pipeline.thermal_pipeline),
+            pipeline_cache.get_compute_pipeline(pipeline.erode_pipeline),
+        ) {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+            // adds the slumping to the erosion of `update` before it's applied
+            pass.set_pipeline(thermal);
+            for chunk in awake() {
+                pass.set_bind_group(0, &chunk.textures, &[]);
+                pass.set_bind_group(1, &chunk.halo, &[]);
+                pass.dispatch_workgroups(chunk.workgroups.x, chunk.workgroups.y, 1);
+            }
+            pass.set_pipeline(erode);
+            for chunk in awake() {
+                pass.set_bind_group(0, &chunk.textures, &[]);
+                pass.dispatch_workgroups(chunk.workgroups.x, chunk.workgroups.y, 1);
+            }
+        }
+
+        if let Some(suitability) =
+            pipeline_cache.get_compute_pipeline(pipeline.suitability_pipeline)
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+            pass.set_pipeline(suitability);
+            for chunk in awake() {
+                pass.set_bind_group(0, &chunk.textures, &[]);
+                pass.set_bind_group(1, &chunk.halo, &[]);
+                pass.dispatch_workgroups(chunk.workgroups.x, chunk.workgroups.y, 1);
+            }
+        }
+
+        if let Some(normals) = pipeline_cache.get_compute_pipeline(pipeline.normals_pipeline) {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+            pass.set_pipeline(normals);
+            for chunk in chunks {
+                pass.set_bind_group(0, &chunk.textures, &[]);
+                pass.set_bind_group(1, &chunk.halo, &[]);
+                pass.dispatch_workgroups(chunk.workgroups.x, chunk.workgroups.y, 1);
+            }
+        }
+
+        if let Some(caustics) = pipeline_cache.get_compute_pipeline(pipeline.caustics_pipeline) {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+            pass.set_pipeline(caustics);
+            for chunk in chunks {
+                pass.set_bind_group(0, &chunk.textures, &[]);
+                pass.set_bind_group(1, &chunk.halo, &[]);
+                pass.dispatch_workgroups(chunk.workgroups.x, chunk.workgroups.y, 1);
+            }
+        }
+
+        if let Some(tile_bounds) =
+            pipeline_cache.get_compute_pipeline(pipeline.tile_bounds_pipeline)
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+            pass.set_pipeline(tile_bounds);
+            for chunk in chunks {
+                pass.set_bind_group(0, &chunk.textures, &[]);
+                pass.set_bind_group(1, &chunk.tile_bounds, &[]);
+                // a workgroup per tile
+                pass.dispatch_workgroups(chunk.tiles.x, chunk.tiles.y, 1);
+            }
+        }
+
+        if world
+            .get_resource::<DebugView>()
+            .is_some_and(|view| view.enabled)
+        {
+            if let Some(debug_view) =
+                pipeline_cache.get_compute_pipeline(pipeline.debug_view_pipeline)
+            {
+                let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+                pass.set_pipeline(debug_view);
+                for chunk in chunks {
+                    pass.set_bind_group(0, &chunk.textures, &[]);
+                    pass.dispatch_workgroups(chunk.workgroups.x, chunk.workgroups.y, 1);
+                }
+            }
+        }
+
+        if let Some(timestamps) = timestamps {
+            timestamps.end(encoder, TimedPass::Fluid);
+        }
+
+        world
+            .resource::<FluidChunkFeedback>()
+            .0
+            .lock()
+            .unwrap()
+            .extend(
+                chunks
+                    .iter()
+                    .filter(|chunk| chunk.state != ChunkState::Awake)
+                    .map(|chunk| (chunk.coord, chunk.state)),
+            );
+
+        Ok(())
+    }
+}
+
+// Add this system to handle sphere input
+pub fn sphere_input_map(
+    mut events: EventWriter<SphereControlEvent>,
+    keyboard: Res<Input<KeyCode>>,
+    controllers: Query<&SphereController>,
+    player: Query<&Transform, With<Player>>,
+    camera: Query<&Transform, With<OrbitCameraController>>,
+) {
+    // Can only control one sphere at a time.
+    let controller = if let Some(controller) = controllers.iter().find(|c| c.enabled) {
+        controller
+    } else {
+        return;
+    };
+
+    let SphereController {
+        translate_sensitivity,
+        ..
+    } = *controller;
+
+    let mut view_direction = player.single().translation - camera.single().translation;
+    view_direction.y = 0.0;
+    view_direction = view_direction.normalize();
+
+    let left = Transform::from_rotation(Quat::from_euler(EulerRot::XYZ, 0.0, PI / 2.0, 0.0))
+        * view_direction;
+
+    for (key, dir) in [
+        (KeyCode::W, view_direction),
+        (KeyCode::A, left),
+        (KeyCode::S, -view_direction),
+        (KeyCode::D, -left),
+    ]
+    .iter()
+    .cloned()
+    {
+        if keyboard.pressed(key) {
+            events.send(SphereControlEvent::Translate(translate_sensitivity * dir));
+        }
+    }
+}
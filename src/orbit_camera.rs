@@ -1,205 +1,206 @@
-use smooth_bevy_cameras::{LookAngles, LookTransform, LookTransformBundle, Smoother};
-
-use bevy::{
-    app::prelude::*,
-    ecs::{bundle::Bundle, prelude::*},
-    input::{
-        mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
-        prelude::*,
-    },
-    math::prelude::*,
-    time::Time,
-    transform::components::Transform,
-};
-
-#[derive(Default)]
-pub struct OrbitCameraPlugin {
-    pub override_input_system: bool,
-}
-
-impl OrbitCameraPlugin {
-    pub fn new(override_input_system: bool) -> Self {
-        Self {
-            override_input_system,
-        }
-    }
-}
-
-impl Plugin for OrbitCameraPlugin {
-    fn build(&self, app: &mut App) {
-        let app = app
-            // .add_systems(PreUpdate, on_controller_enabled_changed)
-            .add_systems(Update, control_system)
-            .add_event::<ControlEvent>();
-
-        if !self.override_input_system {
-            app.add_systems(Update, default_input_map);
-        }
-    }
-}
-
-#[derive(Bundle)]
-pub struct OrbitCameraBundle {
-    controller: OrbitCameraController,
-    look_transform: LookTransformBundle,
-    transform: Transform,
-}
-
-impl OrbitCameraBundle {
-    pub fn new(controller: OrbitCameraController, eye: Vec3, target: Vec3, up: Vec3) -> Self {
-        // Make sure the transform is consistent with the controller to start.
-        let transform = Transform::from_translation(eye).looking_at(target, up);
-
-        Self {
-            controller,
-            look_transform: LookTransformBundle {
-                transform: LookTransform::new(eye, target, up),
-                smoother: Smoother::new(controller.smoothing_weight),
-            },
-            transform,
-        }
-    }
-}
-
-/// A 3rd person camera that orbits around the target.
-#[derive(Clone, Component, Copy, Debug)]
-#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
-pub struct OrbitCameraController {
-    pub enabled: bool,
-    pub mouse_rotate_sensitivity: Vec2,
-    pub mouse_translate_sensitivity: Vec2,
-    pub mouse_wheel_zoom_sensitivity: f32,
-    pub pixels_per_line: f32,
-    pub smoothing_weight: f32,
-}
-
-impl Default for OrbitCameraController {
-    fn default() -> Self {
-        Self {
-            mouse_rotate_sensitivity: Vec2::splat(0.08),
-            mouse_translate_sensitivity: Vec2::splat(0.1),
-            mouse_wheel_zoom_sensitivity: 0.2,
-            smoothing_weight: 0.8,
-            enabled: true,
-            pixels_per_line: 53.0,
-        }
-    }
-}
-
-#[derive(Event)]
-pub enum ControlEvent {
-    Orbit(Vec2),
-    TranslateTarget(Vec2),
-    Zoom(f32),
-    NewTarget(Vec3),
-}
-
-// #[macro_use]
-// mod macros {
-//     #[macro_export]
-//     macro_rules! define_on_controller_enabled_changed(($ControllerStruct:ty) => {
-//         fn on_controller_enabled_changed(
-//             mut smoothers: Query<(&mut Smoother, &$ControllerStruct), Changed<$ControllerStruct>>,
-//         ) {
-//             for (mut smoother, controller) in smoothers.iter_mut() {
-//                 smoother.set_enabled(controller.enabled);
-//             }
-//         }
-//     });
-// }
-
-// define_on_controller_enabled_changed!(OrbitCameraController);
-
-pub fn default_input_map(
-    mut events: EventWriter<ControlEvent>,
-    mut mouse_wheel_reader: EventReader<MouseWheel>,
-    mut mouse_motion_events: EventReader<MouseMotion>,
-    mouse_buttons: Res<Input<MouseButton>>,
-    keyboard: Res<Input<KeyCode>>,
-    controllers: Query<&OrbitCameraController>,
-) {
-    // Can only control one camera at a time.
-    let controller = if let Some(controller) = controllers.iter().find(|c| c.enabled) {
-        controller
-    } else {
-        return;
-    };
-    let OrbitCameraController {
-        mouse_rotate_sensitivity,
-        mouse_translate_sensitivity,
-        mouse_wheel_zoom_sensitivity,
-        pixels_per_line,
-        ..
-    } = *controller;
-
-    let mut cursor_delta = Vec2::ZERO;
-    for event in mouse_motion_events.iter() {
-        cursor_delta += event.delta;
-    }
-
-    // if keyboard.pressed(KeyCode::ControlLeft) {
-    events.send(ControlEvent::Orbit(mouse_rotate_sensitivity * cursor_delta));
-    // }
-
-    // if mouse_buttons.pressed(MouseButton::Right) {
-    //     events.send(ControlEvent::TranslateTarget(
-    //         mouse_translate_sensitivity * cursor_delta,
-    //     ));
-    // }
-
-    let mut scalar = 1.0;
-    for event in mouse_wheel_reader.iter() {
-        // scale the event magnitude per pixel or per line
-        let scroll_amount = match event.unit {
-            MouseScrollUnit::Line => event.y,
-            MouseScrollUnit::Pixel => event.y / pixels_per_line,
-        };
-        scalar *= 1.0 - scroll_amount * mouse_wheel_zoom_sensitivity;
-    }
-    events.send(ControlEvent::Zoom(scalar));
-}
-
-pub fn control_system(
-    time: Res<Time>,
-    mut events: EventReader<ControlEvent>,
-    mut cameras: Query<(&OrbitCameraController, &mut LookTransform, &Transform)>,
-) {
-    // Can only control one camera at a time.
-    let (mut transform, scene_transform) =
-        if let Some((_, transform, scene_transform)) = cameras.iter_mut().find(|c| c.0.enabled) {
-            (transform, scene_transform)
-        } else {
-            return;
-        };
-
-    if let Some(look_dir) = transform.look_direction() {
-        let mut look_angles = LookAngles::from_vector(-look_dir);
-        let mut radius_scalar = 1.0;
-        let radius = transform.radius();
-
-        let dt = time.delta_seconds();
-        for event in events.iter() {
-            match event {
-                ControlEvent::Orbit(delta) => {
-                    look_angles.add_yaw(dt * -delta.x);
-                    look_angles.add_pitch(dt * delta.y);
-                }
-                ControlEvent::TranslateTarget(delta) => {
-                    let right_dir = scene_transform.rotation * -Vec3::X;
-                    let up_dir = scene_transform.rotation * Vec3::Y;
-                    transform.target += dt * delta.x * right_dir + dt * delta.y * up_dir;
-                }
-                ControlEvent::Zoom(scalar) => {
-                    radius_scalar *= scalar;
-                }
-                ControlEvent::NewTarget(target) => {
-                    transform.target = *target;
-                }
-            }
-        }
-        look_angles.assert_not_looking_up();
-
-        let new_radius = (radius_scalar * radius).min(1000000.0).max(0.001);
-        transform.eye = transform.target + new_radius * look_angles.unit_vector();
-    }
-}
+use smooth_bevy_cameras::{LookAngles, LookTransform, LookTransformBundle, Smoother};
+
+use bevy::{
+    app::prelude::*,
+    ecs::{bundle::Bundle, prelude::*},
+    input::{
+        mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+        prelude::*,
+    },
+    math::prelude::*,
+    reflect::Reflect,
+    time::Time,
+    transform::components::Transform,
+};
+
+#[derive(Default)]
+pub struct OrbitCameraPlugin {
+    pub override_input_system: bool,
+}
+
+impl OrbitCameraPlugin {
+    pub fn new(override_input_system: bool) -> Self {
+        Self {
+            override_input_system,
+        }
+    }
+}
+
+impl Plugin for OrbitCameraPlugin {
+    fn build(&self, app: &mut App) {
+        let app = app
+            // .add_systems(PreUpdate, on_controller_enabled_changed)
+            .add_systems(Update, control_system)
+            .add_event::<ControlEvent>();
+
+        if !self.override_input_system {
+            app.add_systems(Update, default_input_map);
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct OrbitCameraBundle {
+    controller: OrbitCameraController,
+    look_transform: LookTransformBundle,
+    transform: Transform,
+}
+
+impl OrbitCameraBundle {
+    pub fn new(controller: OrbitCameraController, eye: Vec3, target: Vec3, up: Vec3) -> Self {
+        // Make sure the transform is consistent with the controller to start.
+        let transform = Transform::from_translation(eye).looking_at(target, up);
+
+        Self {
+            controller,
+            look_transform: LookTransformBundle {
+                transform: LookTransform::new(eye, target, up),
+                smoother: Smoother::new(controller.smoothing_weight),
+            },
+            transform,
+        }
+    }
+}
+
+/// A 3rd person camera that orbits around the target.
+#[derive(Clone, Component, Copy, Debug, Reflect)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct OrbitCameraController {
+    pub enabled: bool,
+    pub mouse_rotate_sensitivity: Vec2,
+    pub mouse_translate_sensitivity: Vec2,
+    pub mouse_wheel_zoom_sensitivity: f32,
+    pub pixels_per_line: f32,
+    pub smoothing_weight: f32,
+}
+
+impl Default for OrbitCameraController {
+    fn default() -> Self {
+        Self {
+            mouse_rotate_sensitivity: Vec2::splat(0.08),
+            mouse_translate_sensitivity: Vec2::splat(0.1),
+            mouse_wheel_zoom_sensitivity: 0.2,
+            smoothing_weight: 0.8,
+            enabled: true,
+            pixels_per_line: 53.0,
+        }
+    }
+}
+
+#[derive(Event)]
+pub enum ControlEvent {
+    Orbit(Vec2),
+    TranslateTarget(Vec2),
+    Zoom(f32),
+    NewTarget(Vec3),
+}
+
+// #[macro_use]
+// mod macros {
+//     #[macro_export]
+//     macro_rules! define_on_controller_enabled_changed(($ControllerStruct:ty) => {
+//         fn on_controller_enabled_changed(
+//             mut smoothers: Query<(&mut Smoother, &$ControllerStruct), Changed<$ControllerStruct>>,
+//         ) {
+//             for (mut smoother, controller) in smoothers.iter_mut() {
+//                 smoother.set_enabled(controller.enabled);
+//             }
+//         }
+//     });
+// }
+
+// define_on_controller_enabled_changed!(OrbitCameraController);
+
+pub fn default_input_map(
+    mut events: EventWriter<ControlEvent>,
+    mut mouse_wheel_reader: EventReader<MouseWheel>,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    keyboard: Res<Input<KeyCode>>,
+    controllers: Query<&OrbitCameraController>,
+) {
+    // Can only control one camera at a time.
+    let controller = if let Some(controller) = controllers.iter().find(|c| c.enabled) {
+        controller
+    } else {
+        return;
+    };
+    let OrbitCameraController {
+        mouse_rotate_sensitivity,
+        mouse_translate_sensitivity,
+        mouse_wheel_zoom_sensitivity,
+        pixels_per_line,
+        ..
+    } = *controller;
+
+    let mut cursor_delta = Vec2::ZERO;
+    for event in mouse_motion_events.iter() {
+        cursor_delta += event.delta;
+    }
+
+    // if keyboard.pressed(KeyCode::ControlLeft) {
+    events.send(ControlEvent::Orbit(mouse_rotate_sensitivity * cursor_delta));
+    // }
+
+    // if mouse_buttons.pressed(MouseButton::Right) {
+    //     events.send(ControlEvent::TranslateTarget(
+    //         mouse_translate_sensitivity * cursor_delta,
+    //     ));
+    // }
+
+    let mut scalar = 1.0;
+    for event in mouse_wheel_reader.iter() {
+        // scale the event magnitude per pixel or per line
+        let scroll_amount = match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / pixels_per_line,
+        };
+        scalar *= 1.0 - scroll_amount * mouse_wheel_zoom_sensitivity;
+    }
+    events.send(ControlEvent::Zoom(scalar));
+}
+
+pub fn control_system(
+    time: Res<Time>,
+    mut events: EventReader<ControlEvent>,
+    mut cameras: Query<(&OrbitCameraController, &mut LookTransform, &Transform)>,
+) {
+    // Can only control one camera at a time.
+    let (mut transform, scene_transform) =
+        if let Some((_, transform, scene_transform)) = cameras.iter_mut().find(|c| c.0.enabled) {
+            (transform, scene_transform)
+        } else {
+            return;
+        };
+
+    if let Some(look_dir) = transform.look_direction() {
+        let mut look_angles = LookAngles::from_vector(-look_dir);
+        let mut radius_scalar = 1.0;
+        let radius = transform.radius();
+
+        let dt = time.delta_seconds();
+        for event in events.iter() {
+            match event {
+                ControlEvent::Orbit(delta) => {
+                    look_angles.add_yaw(dt * -delta.x);
+                    look_angles.add_pitch(dt * delta.y);
+                }
+                ControlEvent::TranslateTarget(delta) => {
+                    let right_dir = scene_transform.rotation * -Vec3::X;
+                    let up_dir = scene_transform.rotation * Vec3::Y;
+                    transform.target += dt * delta.x * right_dir + dt * delta.y * up_dir;
+                }
+                ControlEvent::Zoom(scalar) => {
+                    radius_scalar *= scalar;
+                }
+                ControlEvent::NewTarget(target) => {
+                    transform.target = *target;
+                }
+            }
+        }
+        look_angles.assert_not_looking_up();
+
+        let new_radius = (radius_scalar * radius).min(1000000.0).max(0.001);
+        transform.eye = transform.target + new_radius * look_angles.unit_vector();
+    }
+}
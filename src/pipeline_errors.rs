@@ -0,0 +1,146 @@
+//! Surfaces compute shaders that failed to compile.
+//!
+//! The render world checks the fluid pipelines every frame and hands the ones that failed over
+//! to the main world, which sends a [`PipelineError`] for each and lists them on screen until
+//! they compile again.
+
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{CachedPipelineState, PipelineCache},
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{extract_heights::GenderfluidExtractPipeline, GenderfluidPipeline};
+
+pub struct PipelineErrorPlugin;
+
+impl Plugin for PipelineErrorPlugin {
+    fn build(&self, app: &mut App) {
+        let feedback = PipelineErrorFeedback::default();
+        app.add_event::<PipelineError>()
+            .init_resource::<PipelineErrors>()
+            .insert_resource(feedback.clone())
+            .add_systems(Startup, spawn_pipeline_error_text)
+            .add_systems(
+                Update,
+                (receive_pipeline_errors, update_pipeline_error_text).chain(),
+            );
+        app.sub_app_mut(RenderApp)
+            .insert_resource(feedback)
+            .add_systems(Render, report_pipeline_errors.in_set(RenderSet::Cleanup));
+    }
+}
+
+/// A compute pipeline whose shader failed to compile.
+#[derive(Event, Clone, Debug, PartialEq, Eq)]
+pub struct PipelineError {
+    /// Entry point of the pipeline.
+    pub pipeline: &'static str,
+    pub message: String,
+}
+
+/// The pipelines failing right now.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct PipelineErrors(pub Vec<PipelineError>);
+
+/// The failing pipelines as the render world last saw them, `None` until they change.
+#[derive(Resource, Clone, Default)]
+struct PipelineErrorFeedback(Arc<Mutex<Option<Vec<PipelineError>>>>);
+
+fn report_pipeline_errors(
+    pipeline_cache: Res<PipelineCache>,
+    fluid: Option<Res<GenderfluidPipeline>>,
+    extract: Option<Res<GenderfluidExtractPipeline>>,
+    feedback: Res<PipelineErrorFeedback>,
+    mut reported: Local<Vec<PipelineError>>,
+) {
+    let pipelines = fluid
+        .iter()
+        .flat_map(|fluid| fluid.pipelines())
+        .chain(extract.iter().flat_map(|extract| extract.pipelines()));
+    let errors: Vec<_> = pipelines
+        .filter_map(
+            |(pipeline, id)| match pipeline_cache.get_compute_pipeline_state(id) {
+                CachedPipelineState::Err(err) => Some(PipelineError {
+                    pipeline,
+                    message: err.to_string(),
+                }),
+                _ => None,
+            },
+        )
+        .collect();
+    if *reported != errors {
+        *feedback.0.lock().unwrap() = Some(errors.clone());
+        *reported = errors;
+    }
+}
+
+fn receive_pipeline_errors(
+    feedback: Res<PipelineErrorFeedback>,
+    mut errors: ResMut<PipelineErrors>,
+    mut events: EventWriter<PipelineError>,
+) {
+    let Some(current) = feedback.0.lock().unwrap().take() else {
+        return;
+    };
+    for error in &current {
+        if !errors.0.contains(error) {
+            error!(
+                "the `{}` pipeline failed: {}",
+                error.pipeline, error.message
+            );
+            events.send(error.clone());
+        }
+    }
+    errors.0 = current;
+}
+
+#[derive(Component)]
+struct PipelineErrorText;
+
+fn spawn_pipeline_error_text(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 18.0,
+                color: Color::rgb(1.0, 0.35, 0.3),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            left: Val::Px(8.0),
+            max_width: Val::Percent(60.0),
+            ..default()
+        }),
+        PipelineErrorText,
+    ));
+}
+
+fn update_pipeline_error_text(
+    errors: Res<PipelineErrors>,
+    mut texts: Query<&mut Text, With<PipelineErrorText>>,
+) {
+    if !errors.is_changed() {
+        return;
+    }
+    let message = if errors.0.is_empty() {
+        String::new()
+    } else {
+        let failed: Vec<_> = errors.0.iter().map(|error| error.pipeline).collect();
+        format!(
+            "Shaders failed to compile: {}\n{}",
+            failed.join(", "),
+            errors.0[0].message,
+        )
+    };
+    for mut text in &mut texts {
+        text.sections[0].value = message.clone();
+    }
+}
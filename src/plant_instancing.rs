@@ -0,0 +1,502 @@
+//! Draws all plants of a species in a single instanced draw call.
+//!
+//! The plant glTF is merged into one mesh with its material colours baked into vertex colours,
+//! and every plant becomes one entry of a per-instance vertex buffer, which is only reallocated
+//! when the plants outgrow it. The ground height under
+//! each plant in the focus chunk is looked up from its terrain texture in the vertex shader,
+//! plants in other chunks use the height their chunk read back for them last.
+
+use bevy::{
+    core_pipeline::core_3d::Opaque3d,
+    ecs::{
+        query::QueryItem,
+        system::{lifetimeless::*, SystemParamItem},
+    },
+    gltf::{Gltf, GltfMesh},
+    pbr::{MeshPipeline, MeshPipelineKey, MeshUniform, SetMeshBindGroup, SetMeshViewBindGroup},
+    prelude::*,
+    render::{
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        mesh::{GpuBufferInfo, Indices, MeshVertexBufferLayout, VertexAttributeValues},
+        render_asset::RenderAssets,
+        render_phase::{
+            AddRenderCommand, DrawFunctions, PhaseItem, RenderCommand, RenderCommandResult,
+            RenderPhase, SetItemPipeline, TrackedRenderPass,
+        },
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+        view::ExtractedView,
+        Render, RenderApp, RenderSet,
+    },
+    utils::HashMap,
+};
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    extract_heights::GenderfluidImage, fluid_domain::FluidDomain, Plant, PlantAsset, PlantSpecies,
+};
+
+pub struct PlantInstancingPlugin;
+
+impl Plugin for PlantInstancingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ExtractComponentPlugin::<PlantInstances>::default())
+            .add_systems(Update, (build_plant_mesh, update_plant_instances));
+        app.sub_app_mut(RenderApp)
+            .add_render_command::<Opaque3d, DrawPlants>()
+            .init_resource::<SpecializedMeshPipelines<PlantInstancingPipeline>>()
+            .init_resource::<PlantInstanceBuffers>()
+            .add_systems(
+                Render,
+                (
+                    queue_plants.in_set(RenderSet::Queue),
+                    queue_plant_terrain_bind_group.in_set(RenderSet::Queue),
+                    prepare_plant_instance_buffers.in_set(RenderSet::Prepare),
+                ),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<PlantInstancingPipeline>();
+    }
+}
+
+/// Per-instance data of all plants drawn by this entity.
+#[derive(Component, Default, Clone, Deref, DerefMut)]
+pub struct PlantInstances(pub Vec<PlantInstance>);
+
+impl ExtractComponent for PlantInstances {
+    type Query = &'static PlantInstances;
+    type Filter = ();
+    type Out = Self;
+
+    fn extract_component(item: QueryItem<'_, Self::Query>) -> Option<Self> {
+        Some(item.clone())
+    }
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct PlantInstance {
+    /// World position, `y` is an offset above the terrain inside the focus chunk.
+    pub position: Vec3,
+    pub scale: f32,
+    pub color: [f32; 4],
+}
+
+/// Merges all primitives of the plant glTF into a single mesh, once it has loaded.
+fn build_plant_mesh(
+    mut commands: Commands,
+    plant_asset: Res<PlantAsset>,
+    gltfs: Res<Assets<Gltf>>,
+    gltf_meshes: Res<Assets<GltfMesh>>,
+    materials: Res<Assets<StandardMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    renderers: Query<Entity, (With<PlantInstances>, Without<Handle<Mesh>>)>,
+) {
+    if renderers.is_empty() {
+        return;
+    }
+    let Some(gltf) = gltfs.get(&plant_asset.0) else {
+        return;
+    };
+
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut normals: Vec<[f32; 3]> = vec![];
+    let mut colors: Vec<[f32; 4]> = vec![];
+    let mut indices: Vec<u32> = vec![];
+    for gltf_mesh in gltf.meshes.iter().filter_map(|m| gltf_meshes.get(m)) {
+        for primitive in &gltf_mesh.primitives {
+            let Some(mesh) = meshes.get(&primitive.mesh) else {
+                return;
+            };
+            let (
+                Some(VertexAttributeValues::Float32x3(primitive_positions)),
+                Some(VertexAttributeValues::Float32x3(primitive_normals)),
+            ) = (
+                mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+                mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+            ) else {
+                continue;
+            };
+
+            // Bake the material into the vertex colours, emissive included so the flower
+            // keeps glowing.
+            let color = primitive
+                .material
+                .as_ref()
+                .and_then(|m| materials.get(m))
+                .map(|m| {
+                    Vec4::from(m.base_color.as_linear_rgba_f32())
+                        + Vec4::from(m.emissive.as_linear_rgba_f32()) * Vec4::new(1.0, 1.0, 1.0, 0.0)
+                })
+                .unwrap_or(Vec4::ONE);
+
+            let offset = positions.len() as u32;
+            match mesh.indices() {
+                Some(primitive_indices) => {
+                    indices.extend(primitive_indices.iter().map(|i| offset + i as u32))
+                }
+                None => indices.extend(offset..offset + primitive_positions.len() as u32),
+            }
+            positions.extend_from_slice(primitive_positions);
+            normals.extend_from_slice(primitive_normals);
+            colors.extend(std::iter::repeat(color.to_array()).take(primitive_positions.len()));
+        }
+    }
+
+    // Rest the plant on its lowest point so it sits on the terrain at any scale.
+    let min_y = positions.iter().map(|p| p[1]).fold(f32::INFINITY, f32::min);
+    for position in &mut positions {
+        position[1] -= min_y;
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    let mesh = meshes.add(mesh);
+
+    for entity in &renderers {
+        commands.entity(entity).insert(mesh.clone());
+    }
+}
+
+fn update_plant_instances(
+    plants: Query<(&Transform, &Plant, &Visibility)>,
+    species: Res<PlantSpecies>,
+    genderfluid_image: Res<GenderfluidImage>,
+    mut renderers: Query<&mut PlantInstances>,
+) {
+    let focus_domain = genderfluid_image.focus_domain;
+    let wilted = Vec4::from(species.wilted_tint.as_linear_rgba_f32());
+    let healthy = Vec4::from(species.healthy_tint.as_linear_rgba_f32());
+    let instances = || {
+        plants
+            .iter()
+            .filter(|(_, _, visibility)| **visibility != Visibility::Hidden)
+            .map(|(transform, plant, _)| PlantInstance {
+                position: if focus_domain
+                    .contains_texel(focus_domain.world_to_texel(transform.translation))
+                {
+                    Vec3::new(transform.translation.x, 0.0, transform.translation.z)
+                } else {
+                    transform.translation
+                },
+                scale: transform.scale.x,
+                color: wilted
+                    .lerp(healthy, plant.health.clamp(0.0, 1.0))
+                    .to_array(),
+            })
+    };
+
+    // refilled in place, so the instances keep their allocation
+    for mut renderer in &mut renderers {
+        renderer.0.clear();
+        renderer.0.extend(instances());
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_plants(
+    opaque_3d_draw_functions: Res<DrawFunctions<Opaque3d>>,
+    plant_pipeline: Res<PlantInstancingPipeline>,
+    msaa: Res<Msaa>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<PlantInstancingPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    meshes: Res<RenderAssets<Mesh>>,
+    plant_meshes: Query<(Entity, &MeshUniform, &Handle<Mesh>), With<PlantInstances>>,
+    mut views: Query<(&ExtractedView, &mut RenderPhase<Opaque3d>)>,
+) {
+    let draw_plants = opaque_3d_draw_functions.read().id::<DrawPlants>();
+
+    let msaa_key = MeshPipelineKey::from_msaa_samples(msaa.samples());
+
+    for (view, mut opaque_phase) in &mut views {
+        let view_key = msaa_key | MeshPipelineKey::from_hdr(view.hdr);
+        let rangefinder = view.rangefinder3d();
+        for (entity, mesh_uniform, mesh_handle) in &plant_meshes {
+            if let Some(mesh) = meshes.get(mesh_handle) {
+                let key =
+                    view_key | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology);
+                let pipeline = match pipelines.specialize(
+                    &pipeline_cache,
+                    &plant_pipeline,
+                    key,
+                    &mesh.layout,
+                ) {
+                    Ok(pipeline) => pipeline,
+                    Err(err) => {
+                        error!("can't specialize the plant pipeline: {err}");
+                        continue;
+                    }
+                };
+                opaque_phase.add(Opaque3d {
+                    entity,
+                    pipeline,
+                    draw_function: draw_plants,
+                    distance: rangefinder.distance(&mesh_uniform.transform),
+                });
+            }
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct PlantInstanceBuffer {
+    buffer: Buffer,
+    length: usize,
+}
+
+/// The instance buffer of every renderer, kept across frames since the render world entities
+/// are spawned again each frame.
+#[derive(Resource, Default)]
+struct PlantInstanceBuffers(HashMap<Entity, BufferVec<PlantInstance>>);
+
+fn prepare_plant_instance_buffers(
+    mut commands: Commands,
+    query: Query<(Entity, &PlantInstances)>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut buffers: ResMut<PlantInstanceBuffers>,
+) {
+    buffers.0.retain(|entity, _| query.contains(*entity));
+    for (entity, instances) in &query {
+        let buffer = buffers.0.entry(entity).or_insert_with(|| {
+            let mut buffer = BufferVec::new(BufferUsages::VERTEX);
+            buffer.set_label(Some("plant instance buffer"));
+            buffer
+        });
+        buffer.clear();
+        buffer.extend(instances.iter().copied());
+        // grow in big steps, so plants spawning one by one don't reallocate it every frame
+        buffer.reserve(instances.len().next_power_of_two(), &render_device);
+        buffer.write_buffer(&render_device, &render_queue);
+        if let Some(gpu_buffer) = buffer.buffer() {
+            commands.entity(entity).insert(PlantInstanceBuffer {
+                buffer: gpu_buffer.clone(),
+                length: instances.len(),
+            });
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct PlantTerrainBindGroup {
+    bind_group: BindGroup,
+    /// What the bind group was built from, it's only built again when either changes.
+    terrain_height: TextureViewId,
+    focus_domain: FluidDomain,
+}
+
+fn queue_plant_terrain_bind_group(
+    mut commands: Commands,
+    pipeline: Res<PlantInstancingPipeline>,
+    gpu_images: Res<RenderAssets<Image>>,
+    genderfluid_image: Res<GenderfluidImage>,
+    render_device: Res<RenderDevice>,
+    current: Option<Res<PlantTerrainBindGroup>>,
+) {
+    let Some(terrain_height) = gpu_images.get(&genderfluid_image.terrain_height) else {
+        commands.remove_resource::<PlantTerrainBindGroup>();
+        return;
+    };
+    if current.is_some_and(|current| {
+        current.terrain_height == terrain_height.texture_view.id()
+            && current.focus_domain == genderfluid_image.focus_domain
+    }) {
+        return;
+    }
+    let domain = render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("plant fluid domain"),
+        contents: bytemuck::bytes_of(&genderfluid_image.focus_domain.uniform()),
+        usage: BufferUsages::UNIFORM,
+    });
+
+    let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+        label: Some("plant terrain bind group"),
+        layout: &pipeline.terrain_layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&terrain_height.texture_view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: domain.as_entire_binding(),
+            },
+        ],
+    });
+    commands.insert_resource(PlantTerrainBindGroup {
+        bind_group,
+        terrain_height: terrain_height.texture_view.id(),
+        focus_domain: genderfluid_image.focus_domain,
+    });
+}
+
+#[derive(Resource)]
+pub struct PlantInstancingPipeline {
+    shader: Handle<Shader>,
+    mesh_pipeline: MeshPipeline,
+    terrain_layout: BindGroupLayout,
+}
+
+impl FromWorld for PlantInstancingPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let shader = world
+            .resource::<AssetServer>()
+            .load("shaders/plant_instancing.wgsl");
+        let terrain_layout =
+            world
+                .resource::<RenderDevice>()
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: Some("plant terrain layout"),
+                    entries: &[
+                        // terrain_height
+                        BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: ShaderStages::VERTEX,
+                            ty: BindingType::Texture {
+                                sample_type: TextureSampleType::Float { filterable: false },
+                                view_dimension: TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        // domain
+                        BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: ShaderStages::VERTEX,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+
+        PlantInstancingPipeline {
+            shader,
+            mesh_pipeline: world.resource::<MeshPipeline>().clone(),
+            terrain_layout,
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for PlantInstancingPipeline {
+    type Key = MeshPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut descriptor = self.mesh_pipeline.specialize(key, layout)?;
+
+        // The mesh pipeline puts the mesh into bind group 1 when there's no material.
+        descriptor
+            .vertex
+            .shader_defs
+            .push("MESH_BINDGROUP_1".into());
+        descriptor.vertex.shader = self.shader.clone();
+        descriptor.vertex.buffers.push(VertexBufferLayout {
+            array_stride: std::mem::size_of::<PlantInstance>() as u64,
+            step_mode: VertexStepMode::Instance,
+            attributes: vec![
+                // Locations below 8 are used by the mesh attributes.
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: 0,
+                    shader_location: 8,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: VertexFormat::Float32x4.size(),
+                    shader_location: 9,
+                },
+            ],
+        });
+        descriptor.fragment.as_mut().unwrap().shader = self.shader.clone();
+        descriptor.layout.push(self.terrain_layout.clone());
+        Ok(descriptor)
+    }
+}
+
+type DrawPlants = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    SetPlantTerrainBindGroup<2>,
+    DrawMeshInstanced,
+);
+
+pub struct SetPlantTerrainBindGroup<const I: usize>;
+
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetPlantTerrainBindGroup<I> {
+    type Param = Option<SRes<PlantTerrainBindGroup>>;
+    type ViewWorldQuery = ();
+    type ItemWorldQuery = ();
+
+    #[inline]
+    fn render<'w>(
+        _item: &P,
+        _view: (),
+        _entity: (),
+        bind_group: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        // missing until the terrain texture of the focus chunk is uploaded
+        let Some(bind_group) = bind_group else {
+            return RenderCommandResult::Failure;
+        };
+        pass.set_bind_group(I, &bind_group.into_inner().bind_group, &[]);
+        RenderCommandResult::Success
+    }
+}
+
+pub struct DrawMeshInstanced;
+
+impl<P: PhaseItem> RenderCommand<P> for DrawMeshInstanced {
+    type Param = SRes<RenderAssets<Mesh>>;
+    type ViewWorldQuery = ();
+    type ItemWorldQuery = (Read<Handle<Mesh>>, Read<PlantInstanceBuffer>);
+
+    #[inline]
+    fn render<'w>(
+        _item: &P,
+        _view: (),
+        (mesh_handle, instance_buffer): (&'w Handle<Mesh>, &'w PlantInstanceBuffer),
+        meshes: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let gpu_mesh = match meshes.into_inner().get(mesh_handle) {
+            Some(gpu_mesh) => gpu_mesh,
+            None => return RenderCommandResult::Failure,
+        };
+        if instance_buffer.length == 0 {
+            return RenderCommandResult::Success;
+        }
+
+        pass.set_vertex_buffer(0, gpu_mesh.vertex_buffer.slice(..));
+        pass.set_vertex_buffer(1, instance_buffer.buffer.slice(..));
+
+        match &gpu_mesh.buffer_info {
+            GpuBufferInfo::Indexed {
+                buffer,
+                index_format,
+                count,
+            } => {
+                pass.set_index_buffer(buffer.slice(..), 0, *index_format);
+                pass.draw_indexed(0..*count, 0, 0..instance_buffer.length as u32);
+            }
+            GpuBufferInfo::NonIndexed => {
+                pass.draw(0..gpu_mesh.vertex_count, 0..instance_buffer.length as u32);
+            }
+        }
+        RenderCommandResult::Success
+    }
+}
@@ -0,0 +1,207 @@
+//! A copy of the opaque scene for the water to refract and reflect.
+//!
+//! Bevy doesn't hand materials the colour of what was drawn before them, so once the opaque
+//! pass is done the view's main texture is blitted into [`SceneColor`], an ordinary image the
+//! water material binds.
+
+use bevy::{
+    core_pipeline::{
+        blit::{BlitPipeline, BlitPipelineKey},
+        core_3d,
+    },
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::RenderAssets,
+        render_graph::{NodeRunError, RenderGraphApp, RenderGraphContext, ViewNode, ViewNodeRunner},
+        render_resource::*,
+        renderer::RenderContext,
+        view::ViewTarget,
+        RenderApp,
+    },
+    window::PrimaryWindow,
+};
+
+use crate::water_material::WaterMaterial;
+
+const SCENE_COLOR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+pub struct SceneColorPlugin;
+
+impl Plugin for SceneColorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SceneColor>()
+            .add_plugins(ExtractResourcePlugin::<SceneColor>::default())
+            .add_systems(PostUpdate, resize_scene_color);
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .add_render_graph_node::<ViewNodeRunner<SceneColorNode>>(
+                core_3d::graph::NAME,
+                SceneColorNode::NAME,
+            )
+            .add_render_graph_edges(
+                core_3d::graph::NAME,
+                &[
+                    core_3d::graph::node::MAIN_OPAQUE_PASS,
+                    SceneColorNode::NAME,
+                    core_3d::graph::node::MAIN_TRANSPARENT_PASS,
+                ],
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<SceneColorPipeline>();
+    }
+}
+
+/// The opaque scene of the primary camera, as it was before the transparent pass.
+#[derive(Resource, ExtractResource, Clone)]
+pub struct SceneColor(pub Handle<Image>);
+
+impl FromWorld for SceneColor {
+    fn from_world(world: &mut World) -> Self {
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[0; 8],
+            SCENE_COLOR_FORMAT,
+        );
+        image.texture_descriptor.usage = TextureUsages::COPY_DST
+            | TextureUsages::TEXTURE_BINDING
+            | TextureUsages::RENDER_ATTACHMENT;
+        SceneColor(world.resource_mut::<Assets<Image>>().add(image))
+    }
+}
+
+/// Keeps the scene colour the size of the window.
+fn resize_scene_color(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    scene_color: Res<SceneColor>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<WaterMaterial>>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let size = Extent3d {
+        width: window.physical_width().max(1),
+        height: window.physical_height().max(1),
+        depth_or_array_layers: 1,
+    };
+    let Some(image) = images.get_mut(&scene_color.0) else {
+        return;
+    };
+    if image.texture_descriptor.size == size {
+        return;
+    }
+    image.resize(size);
+
+    // their bind groups hold on to the old texture, have them rebuilt
+    let ids: Vec<_> = materials
+        .iter()
+        .filter(|(_, material)| material.scene_color.as_ref() == Some(&scene_color.0))
+        .map(|(id, _)| id)
+        .collect();
+    for id in ids {
+        materials.get_mut(&Handle::weak(id));
+    }
+}
+
+#[derive(Resource)]
+struct SceneColorPipeline(CachedRenderPipelineId);
+
+impl FromWorld for SceneColorPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let id = world.resource_scope(
+            |world, mut pipelines: Mut<SpecializedRenderPipelines<BlitPipeline>>| {
+                pipelines.specialize(
+                    world.resource::<PipelineCache>(),
+                    world.resource::<BlitPipeline>(),
+                    BlitPipelineKey {
+                        texture_format: SCENE_COLOR_FORMAT,
+                        blend_state: None,
+                        samples: 1,
+                    },
+                )
+            },
+        );
+        SceneColorPipeline(id)
+    }
+}
+
+#[derive(Default)]
+struct SceneColorNode;
+
+impl SceneColorNode {
+    const NAME: &str = "scene_color";
+}
+
+impl ViewNode for SceneColorNode {
+    type ViewQuery = &'static ViewTarget;
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        target: QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let blit_pipeline = world.resource::<BlitPipeline>();
+        let Some(pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(world.resource::<SceneColorPipeline>().0)
+        else {
+            return Ok(());
+        };
+        let Some(scene_color) = world
+            .resource::<RenderAssets<Image>>()
+            .get(&world.resource::<SceneColor>().0)
+        else {
+            return Ok(());
+        };
+
+        let bind_group = render_context
+            .render_device()
+            .create_bind_group(&BindGroupDescriptor {
+                label: Some("scene color bind group"),
+                layout: &blit_pipeline.texture_bind_group,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(target.main_texture_view()),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(&blit_pipeline.sampler),
+                    },
+                ],
+            });
+
+        let mut render_pass =
+            render_context
+                .command_encoder()
+                .begin_render_pass(&RenderPassDescriptor {
+                    label: Some("scene_color_pass"),
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: &scene_color.texture_view,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::Clear(Default::default()),
+                            store: true,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        Ok(())
+    }
+}
@@ -0,0 +1,357 @@
+//! The terrain and water the chunks start out with.
+//!
+//! The `init` pass builds the terrain from layers of noise described by [`TerrainGenerator`].
+//! A few presets cover the common looks, one of them can be picked at startup with
+//! `--terrain <preset>` and the noise reseeded with `--seed <seed>`.
+
+use std::str::FromStr;
+
+use bevy::{prelude::*, render::render_resource::ShaderType};
+use bytemuck::{Pod, Zeroable};
+
+use crate::{fluid_chunks::ResetSimulation, fluid_domain::FluidDomain};
+
+pub struct TerrainGeneratorPlugin;
+
+impl Plugin for TerrainGeneratorPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<TerrainGenerator>()
+            .register_type::<TerrainPreset>()
+            .add_systems(Update, apply_reset);
+        if !app.world.contains_resource::<TerrainGenerator>() {
+            app.insert_resource(TerrainGenerator::from_args(std::env::args().skip(1)));
+        }
+    }
+}
+
+/// Layers of noise the terrain is generated from.
+///
+/// Distances are in texels of the whole domain, so the chunks line up, and heights in world
+/// units.
+#[derive(Resource, Reflect, Clone, Debug)]
+#[reflect(Resource)]
+pub struct TerrainGenerator {
+    pub preset: TerrainPreset,
+    /// Shifts the noise, every seed gives a different terrain.
+    pub seed: u32,
+    /// Frequency of the first octave along x and z.
+    pub frequency: Vec2,
+    /// Number of octaves of fBm noise added up.
+    pub octaves: u32,
+    /// Frequency of every octave over the previous one.
+    pub lacunarity: f32,
+    /// Amplitude of every octave over the previous one.
+    pub persistence: f32,
+    /// Height of the noise around [`TerrainGenerator::base_height`].
+    pub amplitude: f32,
+    pub base_height: f32,
+    /// Blend from plain noise at 0 to ridged noise, sharp crests and round valleys, at 1.
+    pub ridged: f32,
+    /// How far the noise is pushed around by another noise, zero turns the warp off.
+    pub warp_strength: f32,
+    pub warp_frequency: f32,
+    /// How far the terrain sinks towards the edges of the domain, zero turns it off.
+    pub island_falloff: f32,
+    /// Depth of the river beds carved along the zero crossings of a low frequency noise, zero
+    /// turns them off.
+    pub river_depth: f32,
+    /// Width of the river beds, in the units of that noise.
+    pub river_width: f32,
+    pub river_frequency: f32,
+    /// Water surface height the low terrain is flooded up to.
+    pub sea_level: f32,
+    /// How much water is scattered over the terrain in puddles.
+    pub puddles: f32,
+    pub puddle_frequency: f32,
+    /// Width of the strip along the south edge of the domain that starts without puddles.
+    pub dry_margin: f32,
+}
+
+impl Default for TerrainGenerator {
+    fn default() -> Self {
+        TerrainPreset::default().generator()
+    }
+}
+
+impl TerrainGenerator {
+    /// Picks the preset and seed from command line arguments, falling back to the defaults for
+    /// anything missing or not understood.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Self {
+        let mut preset = TerrainPreset::default();
+        let mut seed = 0;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            match name.as_str() {
+                "--terrain" => match value.or_else(|| args.next()).map(|v| v.parse()) {
+                    Some(Ok(parsed)) => preset = parsed,
+                    _ => warn!(
+                        "expected one of {:?} after --terrain",
+                        TerrainPreset::ALL.map(TerrainPreset::name)
+                    ),
+                },
+                "--seed" => match value.or_else(|| args.next()).map(|v| v.parse()) {
+                    Some(Ok(parsed)) => seed = parsed,
+                    _ => warn!("expected a number after --seed"),
+                },
+                _ => {}
+            }
+        }
+        Self {
+            seed,
+            ..preset.generator()
+        }
+    }
+
+    /// Lowest terrain and highest water surface the `init` pass can generate.
+    pub fn height_range(&self) -> Vec2 {
+        // a negative river depth raises a ridge instead
+        let lowest =
+            self.base_height - self.amplitude - self.island_falloff - self.river_depth.max(0.0);
+        let terrain = self.base_height + self.amplitude - self.river_depth.min(0.0);
+        // only the tops of the puddle noise above 0.777 stick out, see `initial_water`
+        let puddles = (self.puddles - 0.777).max(0.0);
+        let highest = terrain.max(self.sea_level) + puddles;
+        Vec2::new(lowest.max(0.0), highest)
+    }
+
+    /// The parameters the `init` pass generates the terrain of `domain` with.
+    pub fn uniform(&self, domain: &FluidDomain) -> TerrainGeneratorUniform {
+        // big odd steps, so close seeds don't give similar terrain, in integers so large seeds
+        // don't round to the same offset
+        let offset = Vec2::new(
+            (self.seed.wrapping_mul(7919) % 65536) as f32,
+            (self.seed.wrapping_mul(6271) % 65536) as f32,
+        );
+        TerrainGeneratorUniform {
+            offset,
+            frequency: self.frequency,
+            octaves: self.octaves,
+            lacunarity: self.lacunarity,
+            persistence: self.persistence,
+            amplitude: self.amplitude,
+            base_height: self.base_height,
+            ridged: self.ridged,
+            warp_strength: self.warp_strength,
+            warp_frequency: self.warp_frequency,
+            island_falloff: self.island_falloff,
+            river_depth: self.river_depth,
+            river_width: self.river_width,
+            river_frequency: self.river_frequency,
+            sea_level: self.sea_level,
+            puddles: self.puddles,
+            puddle_frequency: self.puddle_frequency,
+            dry_margin: self.dry_margin,
+            domain_texels: domain.resolution.as_vec2(),
+            _padding: Vec2::ZERO,
+        }
+    }
+}
+
+/// Switches to the preset and seed a [`ResetSimulation`] asks for before the chunks generate
+/// their terrain again.
+fn apply_reset(mut resets: EventReader<ResetSimulation>, mut generator: ResMut<TerrainGenerator>) {
+    for reset in resets.iter() {
+        if let Some(preset) = reset.preset {
+            *generator = TerrainGenerator {
+                seed: generator.seed,
+                ..preset.generator()
+            };
+        }
+        if let Some(seed) = reset.seed {
+            generator.seed = seed;
+        }
+    }
+}
+
+/// The GPU representation of a [`TerrainGenerator`].
+#[derive(Reflect, Clone, Copy, Debug, Default, ShaderType, Pod, Zeroable)]
+#[repr(C)]
+pub struct TerrainGeneratorUniform {
+    /// Where the noise is read from, derived from [`TerrainGenerator::seed`].
+    pub offset: Vec2,
+    pub frequency: Vec2,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub persistence: f32,
+    pub amplitude: f32,
+    pub base_height: f32,
+    pub ridged: f32,
+    pub warp_strength: f32,
+    pub warp_frequency: f32,
+    pub island_falloff: f32,
+    pub river_depth: f32,
+    pub river_width: f32,
+    pub river_frequency: f32,
+    pub sea_level: f32,
+    pub puddles: f32,
+    pub puddle_frequency: f32,
+    pub dry_margin: f32,
+    /// Size of the whole domain, the island falloff is measured across it.
+    pub domain_texels: Vec2,
+    pub _padding: Vec2,
+}
+
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TerrainPreset {
+    /// A single octave of noise stretched along x with puddles scattered over it.
+    #[default]
+    Classic,
+    /// Rolling hills of warped fBm noise.
+    Hills,
+    /// Ridged noise with sharp crests.
+    Mountains,
+    /// Land sinking into a sea towards the edges of the domain.
+    Islands,
+    /// Hills cut by river beds.
+    Rivers,
+}
+
+impl TerrainPreset {
+    pub const ALL: [TerrainPreset; 5] = [
+        TerrainPreset::Classic,
+        TerrainPreset::Hills,
+        TerrainPreset::Mountains,
+        TerrainPreset::Islands,
+        TerrainPreset::Rivers,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TerrainPreset::Classic => "classic",
+            TerrainPreset::Hills => "hills",
+            TerrainPreset::Mountains => "mountains",
+            TerrainPreset::Islands => "islands",
+            TerrainPreset::Rivers => "rivers",
+        }
+    }
+
+    pub fn generator(self) -> TerrainGenerator {
+        let classic = TerrainGenerator {
+            preset: self,
+            seed: 0,
+            frequency: Vec2::new(0.0052, 0.0152),
+            octaves: 1,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            amplitude: 1.0,
+            base_height: 1.5,
+            ridged: 0.0,
+            warp_strength: 0.0,
+            warp_frequency: 0.004,
+            island_falloff: 0.0,
+            river_depth: 0.0,
+            river_width: 0.05,
+            river_frequency: 0.003,
+            sea_level: 0.0,
+            puddles: 1.0,
+            puddle_frequency: 0.0052,
+            dry_margin: 100.0,
+        };
+        let hills = TerrainGenerator {
+            frequency: Vec2::splat(0.006),
+            octaves: 5,
+            amplitude: 0.8,
+            base_height: 1.2,
+            warp_strength: 20.0,
+            puddles: 0.0,
+            dry_margin: 0.0,
+            ..classic
+        };
+        match self {
+            TerrainPreset::Classic => classic,
+            TerrainPreset::Hills => hills,
+            TerrainPreset::Mountains => TerrainGenerator {
+                frequency: Vec2::splat(0.004),
+                octaves: 6,
+                persistence: 0.45,
+                amplitude: 1.5,
+                base_height: 1.0,
+                ridged: 1.0,
+                warp_strength: 30.0,
+                ..hills
+            },
+            TerrainPreset::Islands => TerrainGenerator {
+                island_falloff: 1.5,
+                sea_level: 0.9,
+                ..hills
+            },
+            TerrainPreset::Rivers => TerrainGenerator {
+                river_depth: 0.4,
+                river_width: 0.06,
+                ..hills
+            },
+        }
+    }
+}
+
+impl FromStr for TerrainPreset {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TerrainPreset::ALL
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn presets_parse_by_name() {
+        for preset in TerrainPreset::ALL {
+            assert_eq!(preset.name().parse(), Ok(preset));
+        }
+        assert_eq!("Islands".parse(), Ok(TerrainPreset::Islands));
+        assert_eq!("volcano".parse::<TerrainPreset>(), Err(()));
+    }
+
+    #[test]
+    fn arguments_pick_preset_and_seed() {
+        let generator = TerrainGenerator::from_args(args(&["--terrain", "rivers", "--seed=7"]));
+        assert_eq!(generator.preset, TerrainPreset::Rivers);
+        assert_eq!(generator.seed, 7);
+        assert_eq!(
+            generator.river_depth,
+            TerrainPreset::Rivers.generator().river_depth
+        );
+
+        let generator = TerrainGenerator::from_args(args(&["--terrain=hills", "--seed", "3"]));
+        assert_eq!(generator.preset, TerrainPreset::Hills);
+        assert_eq!(generator.seed, 3);
+    }
+
+    #[test]
+    fn bad_arguments_fall_back_to_defaults() {
+        let generator =
+            TerrainGenerator::from_args(args(&["--terrain", "volcano", "--seed", "many", "-v"]));
+        assert_eq!(generator.preset, TerrainPreset::default());
+        assert_eq!(generator.seed, 0);
+
+        let generator = TerrainGenerator::from_args(args(&["--terrain"]));
+        assert_eq!(generator.preset, TerrainPreset::default());
+    }
+
+    #[test]
+    fn large_seeds_shift_the_noise() {
+        let domain = FluidDomain::default();
+        let offset = |seed| {
+            TerrainGenerator { seed, ..default() }
+                .uniform(&domain)
+                .offset
+        };
+        assert_ne!(offset(200_000_000), Vec2::ZERO);
+        assert_ne!(offset(200_000_000), offset(200_000_001));
+        assert_ne!(offset(u32::MAX), offset(0));
+    }
+}
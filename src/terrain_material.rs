@@ -0,0 +1,201 @@
+use bevy::{
+    pbr::{MaterialPipeline, MaterialPipelineKey},
+    prelude::*,
+    reflect::{Reflect, TypeUuid},
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_asset::RenderAssets,
+        render_resource::{
+            AsBindGroup, AsBindGroupShaderType, RenderPipelineDescriptor, ShaderRef, ShaderType,
+            SpecializedMeshPipelineError,
+        },
+    },
+};
+
+/// The ground under the water, splatted between grass, sand, rock and mud by height, slope
+/// and how much water stands on it.
+///
+/// The bindings start at 13 to stay clear of the `StandardMaterial` ones `bevy_pbr`'s lighting
+/// functions bring along.
+#[derive(AsBindGroup, Reflect, Debug, Clone, TypeUuid)]
+#[uuid = "5c1e3b0a-1d7e-4f0b-9a53-2f8e6f1c7d42"]
+#[bind_group_data(TerrainMaterialKey)]
+#[uniform(13, TerrainMaterialUniform)]
+#[reflect(Default, Debug)]
+pub struct TerrainMaterial {
+    pub grass: Color,
+    pub rock: Color,
+    pub sand: Color,
+    /// Where water stands on the ground, needs [`TerrainMaterial::water`].
+    pub mud: Color,
+    /// What the albedo is scaled by where the ground is soaked, needs
+    /// [`TerrainMaterial::wetness`].
+    pub wet_darkening: f32,
+    /// Perceptual roughness of soaked ground.
+    pub wet_roughness: f32,
+    /// Terrain below this world height is sand.
+    pub sand_height: f32,
+    /// Terrain steeper than this, as the tangent of its angle, is rock.
+    pub rock_slope: f32,
+    /// Water this deep turns the ground fully into mud.
+    pub mud_depth: f32,
+    /// Width of the blend between two layers, in world units for height and in slope for rock.
+    pub transition: f32,
+    /// How strongly the caustics brighten and darken the ground, needs
+    /// [`TerrainMaterial::caustics`].
+    pub caustics_strength: f32,
+    pub perceptual_roughness: f32,
+    pub reflectance: f32,
+    /// Draws [`TerrainMaterial::debug`] in place of the terrain colours.
+    pub show_debug: bool,
+
+    /// Terrain height the mesh is displaced by.
+    #[texture(14)]
+    #[sampler(15)]
+    pub height: Option<Handle<Image>>,
+    /// Surface normals from the `normals` pass.
+    #[texture(16)]
+    #[sampler(17)]
+    pub normal: Option<Handle<Image>>,
+    /// Water height on top of the terrain, turns the ground into mud when set.
+    #[texture(18)]
+    #[sampler(19)]
+    pub water: Option<Handle<Image>>,
+    /// Light focused onto the ground by the water above, from the `caustics` pass.
+    #[texture(20)]
+    #[sampler(21)]
+    pub caustics: Option<Handle<Image>>,
+    /// How wet the ground still is after the water left, darkens and smoothes it when set.
+    #[texture(22)]
+    #[sampler(23)]
+    pub wetness: Option<Handle<Image>>,
+    /// False colours of a simulation texture from the `debug_view` pass.
+    #[texture(24)]
+    #[sampler(25)]
+    pub debug: Option<Handle<Image>>,
+}
+
+impl Default for TerrainMaterial {
+    fn default() -> Self {
+        TerrainMaterial {
+            grass: Color::hsl(95.0, 0.45, 0.3),
+            rock: Color::hsl(22.0, 0.12, 0.35),
+            sand: Color::hsl(40.0, 0.45, 0.6),
+            mud: Color::hsl(22.0, 0.6, 0.18),
+            wet_darkening: 0.55,
+            wet_roughness: 0.35,
+            sand_height: 0.8,
+            rock_slope: 1.2,
+            mud_depth: 0.02,
+            transition: 0.15,
+            caustics_strength: 0.6,
+            perceptual_roughness: 0.9,
+            reflectance: 0.2,
+            show_debug: false,
+            height: None,
+            normal: None,
+            water: None,
+            caustics: None,
+            wetness: None,
+            debug: None,
+        }
+    }
+}
+
+/// The GPU representation of the uniform data of a [`TerrainMaterial`].
+#[derive(Clone, Default, ShaderType)]
+pub struct TerrainMaterialUniform {
+    pub grass: Vec4,
+    pub rock: Vec4,
+    pub sand: Vec4,
+    pub mud: Vec4,
+    pub wet_darkening: f32,
+    pub wet_roughness: f32,
+    pub sand_height: f32,
+    pub rock_slope: f32,
+    pub mud_depth: f32,
+    pub transition: f32,
+    pub caustics_strength: f32,
+    pub roughness: f32,
+    pub reflectance: f32,
+}
+
+impl AsBindGroupShaderType<TerrainMaterialUniform> for TerrainMaterial {
+    fn as_bind_group_shader_type(&self, _images: &RenderAssets<Image>) -> TerrainMaterialUniform {
+        TerrainMaterialUniform {
+            grass: self.grass.as_linear_rgba_f32().into(),
+            rock: self.rock.as_linear_rgba_f32().into(),
+            sand: self.sand.as_linear_rgba_f32().into(),
+            mud: self.mud.as_linear_rgba_f32().into(),
+            wet_darkening: self.wet_darkening,
+            wet_roughness: self.wet_roughness,
+            sand_height: self.sand_height,
+            rock_slope: self.rock_slope,
+            mud_depth: self.mud_depth,
+            transition: self.transition,
+            caustics_strength: self.caustics_strength,
+            roughness: self.perceptual_roughness,
+            reflectance: self.reflectance,
+        }
+    }
+}
+
+/// The pipeline key for [`TerrainMaterial`].
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct TerrainMaterialKey {
+    mud: bool,
+    caustics: bool,
+    wetness: bool,
+    debug: bool,
+}
+
+impl From<&TerrainMaterial> for TerrainMaterialKey {
+    fn from(material: &TerrainMaterial) -> Self {
+        TerrainMaterialKey {
+            mud: material.water.is_some(),
+            caustics: material.caustics.is_some(),
+            wetness: material.wetness.is_some(),
+            debug: material.show_debug && material.debug.is_some(),
+        }
+    }
+}
+
+impl Material for TerrainMaterial {
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            if key.bind_group_data.mud {
+                fragment.shader_defs.push("TERRAIN_MUD".into());
+            }
+            if key.bind_group_data.caustics {
+                fragment.shader_defs.push("TERRAIN_CAUSTICS".into());
+            }
+            if key.bind_group_data.wetness {
+                fragment.shader_defs.push("TERRAIN_WETNESS".into());
+            }
+            if key.bind_group_data.debug {
+                fragment.shader_defs.push("TERRAIN_DEBUG".into());
+            }
+        }
+        if let Some(label) = &mut descriptor.label {
+            *label = format!("terrain_{}", *label).into();
+        }
+        Ok(())
+    }
+
+    fn vertex_shader() -> ShaderRef {
+        "shaders/terrain_vertex_and_fragment.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/terrain_vertex_and_fragment.wgsl".into()
+    }
+
+    fn prepass_vertex_shader() -> ShaderRef {
+        "shaders/terrain_prepass.wgsl".into()
+    }
+}